@@ -0,0 +1,2 @@
+-- records the download quality each album was fetched with
+ALTER TABLE albums ADD COLUMN quality TEXT NOT NULL DEFAULT 'original';
@@ -0,0 +1,2 @@
+ALTER TABLE albums DROP COLUMN outdated;
+ALTER TABLE albums DROP COLUMN synced_at;
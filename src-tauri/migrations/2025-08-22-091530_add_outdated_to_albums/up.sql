@@ -0,0 +1,4 @@
+-- tracks albums that changed on the server after they were downloaded
+ALTER TABLE albums ADD COLUMN outdated TEXT;
+ALTER TABLE albums ADD COLUMN synced_at TIMESTAMP;
+UPDATE albums SET synced_at = updated_at;
@@ -0,0 +1,14 @@
+ALTER TABLE albums DROP COLUMN production_year;
+ALTER TABLE albums DROP COLUMN genres;
+ALTER TABLE albums DROP COLUMN artists;
+ALTER TABLE albums DROP COLUMN run_time_ticks;
+ALTER TABLE albums DROP COLUMN overview;
+ALTER TABLE albums DROP COLUMN date_created;
+ALTER TABLE albums DROP COLUMN is_favorite;
+ALTER TABLE albums DROP COLUMN play_count;
+
+ALTER TABLE tracks DROP COLUMN disc_number;
+ALTER TABLE tracks DROP COLUMN artists;
+ALTER TABLE tracks DROP COLUMN run_time_ticks;
+ALTER TABLE tracks DROP COLUMN is_favorite;
+ALTER TABLE tracks DROP COLUMN play_count;
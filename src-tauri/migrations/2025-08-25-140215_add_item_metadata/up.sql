@@ -0,0 +1,16 @@
+-- adds richer item metadata to albums and tracks
+-- genres and artists are stored as json arrays
+ALTER TABLE albums ADD COLUMN production_year INTEGER;
+ALTER TABLE albums ADD COLUMN genres TEXT;
+ALTER TABLE albums ADD COLUMN artists TEXT;
+ALTER TABLE albums ADD COLUMN run_time_ticks BIGINT;
+ALTER TABLE albums ADD COLUMN overview TEXT;
+ALTER TABLE albums ADD COLUMN date_created TIMESTAMP;
+ALTER TABLE albums ADD COLUMN is_favorite BOOLEAN NOT NULL DEFAULT 0;
+ALTER TABLE albums ADD COLUMN play_count INTEGER NOT NULL DEFAULT 0;
+
+ALTER TABLE tracks ADD COLUMN disc_number INTEGER;
+ALTER TABLE tracks ADD COLUMN artists TEXT;
+ALTER TABLE tracks ADD COLUMN run_time_ticks BIGINT;
+ALTER TABLE tracks ADD COLUMN is_favorite BOOLEAN NOT NULL DEFAULT 0;
+ALTER TABLE tracks ADD COLUMN play_count INTEGER NOT NULL DEFAULT 0;
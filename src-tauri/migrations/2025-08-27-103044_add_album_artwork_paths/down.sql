@@ -0,0 +1,3 @@
+ALTER TABLE albums DROP COLUMN back_image_path;
+ALTER TABLE albums DROP COLUMN disc_image_path;
+ALTER TABLE albums DROP COLUMN artist_image_path;
@@ -0,0 +1,4 @@
+-- paths for the extra artwork saved alongside the cover
+ALTER TABLE albums ADD COLUMN back_image_path TEXT;
+ALTER TABLE albums ADD COLUMN disc_image_path TEXT;
+ALTER TABLE albums ADD COLUMN artist_image_path TEXT;
@@ -0,0 +1,7 @@
+-- cached server responses for browse, search and item lookups, keyed by url
+CREATE TABLE response_cache (
+  cache_key TEXT PRIMARY KEY NOT NULL,
+  body TEXT NOT NULL,
+  etag TEXT,
+  fetched_at TIMESTAMP NOT NULL
+);
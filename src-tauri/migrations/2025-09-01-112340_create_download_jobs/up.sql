@@ -0,0 +1,8 @@
+-- albums waiting to be downloaded, so the queue survives a restart
+CREATE TABLE download_jobs (
+  id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+  album_id TEXT NOT NULL,
+  user_id TEXT NOT NULL,
+  quality TEXT NOT NULL,
+  created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
@@ -0,0 +1,2 @@
+ALTER TABLE download_jobs DROP COLUMN error;
+ALTER TABLE download_jobs DROP COLUMN status;
@@ -0,0 +1,3 @@
+-- failed jobs are kept so they can be retried
+ALTER TABLE download_jobs ADD COLUMN status TEXT NOT NULL DEFAULT 'queued';
+ALTER TABLE download_jobs ADD COLUMN error TEXT;
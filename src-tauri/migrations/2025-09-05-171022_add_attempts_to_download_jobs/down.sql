@@ -0,0 +1 @@
+ALTER TABLE download_jobs DROP COLUMN attempts;
@@ -0,0 +1 @@
+ALTER TABLE download_jobs ADD COLUMN attempts INTEGER NOT NULL DEFAULT 0;
@@ -0,0 +1 @@
+ALTER TABLE download_jobs ADD COLUMN position INTEGER NOT NULL DEFAULT 0;
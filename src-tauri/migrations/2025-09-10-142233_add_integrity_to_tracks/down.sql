@@ -0,0 +1,2 @@
+ALTER TABLE tracks DROP COLUMN sha256;
+ALTER TABLE tracks DROP COLUMN size;
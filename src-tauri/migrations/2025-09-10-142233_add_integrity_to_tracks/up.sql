@@ -0,0 +1,2 @@
+ALTER TABLE tracks ADD COLUMN size BIGINT;
+ALTER TABLE tracks ADD COLUMN sha256 TEXT;
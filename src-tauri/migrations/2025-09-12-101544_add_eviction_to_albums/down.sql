@@ -0,0 +1,2 @@
+ALTER TABLE albums DROP COLUMN pinned;
+ALTER TABLE albums DROP COLUMN last_played_at;
@@ -0,0 +1,2 @@
+ALTER TABLE albums ADD COLUMN last_played_at TIMESTAMP;
+ALTER TABLE albums ADD COLUMN pinned BOOLEAN NOT NULL DEFAULT 0;
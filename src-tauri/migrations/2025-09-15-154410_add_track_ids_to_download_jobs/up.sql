@@ -0,0 +1 @@
+ALTER TABLE download_jobs ADD COLUMN track_ids TEXT;
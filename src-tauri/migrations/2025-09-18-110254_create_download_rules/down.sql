@@ -0,0 +1,2 @@
+ALTER TABLE albums DROP COLUMN download_rule_id;
+DROP TABLE download_rules;
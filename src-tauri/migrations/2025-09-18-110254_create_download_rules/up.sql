@@ -0,0 +1,9 @@
+-- content to keep downloaded without asking, checked by the rule sync
+CREATE TABLE download_rules (
+  id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+  criteria TEXT NOT NULL,
+  remove_unmatched BOOLEAN NOT NULL DEFAULT 0,
+  created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+ALTER TABLE albums ADD COLUMN download_rule_id INTEGER;
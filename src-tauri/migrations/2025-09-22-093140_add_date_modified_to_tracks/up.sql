@@ -0,0 +1 @@
+ALTER TABLE tracks ADD COLUMN date_modified TIMESTAMP;
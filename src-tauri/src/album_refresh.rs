@@ -0,0 +1,80 @@
+use crate::music_manager::MusicManager;
+use serde::Serialize;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tauri::{AppHandle, Emitter};
+
+const ALBUM_REFRESH_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TrackRename {
+    pub track_id: String,
+    pub from: String,
+    pub to: String,
+}
+
+// what changed on the server since an album was downloaded. sent as
+// "album-refreshed", and returned when a refresh is asked for
+#[derive(Clone, Debug, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AlbumRefresh {
+    pub album_id: String,
+    // set when the album was renamed or moved to another artist
+    pub previous_title: Option<String>,
+    pub previous_artist: Option<String>,
+    pub added: Vec<String>,
+    pub removed: Vec<String>,
+    pub renamed: Vec<TrackRename>,
+    // tracks saved again because the server's copy is newer
+    pub replaced: Vec<String>,
+    pub artwork_changed: bool,
+    // how many added or replaced tracks were queued to download
+    pub queued: usize,
+}
+
+impl AlbumRefresh {
+    pub fn has_changes(&self) -> bool {
+        self.previous_title.is_some()
+            || self.previous_artist.is_some()
+            || !self.added.is_empty()
+            || !self.removed.is_empty()
+            || !self.renamed.is_empty()
+            || !self.replaced.is_empty()
+            || self.artwork_changed
+    }
+}
+
+// The album refresh loop, to be run in a thread. Once at startup and then every
+// ALBUM_REFRESH_INTERVAL, brings downloaded albums the server changed up to date.
+pub fn run_album_refresh(
+    app_handle: AppHandle,
+    music_manager: Arc<MusicManager>,
+    auth_token: Arc<Mutex<Option<String>>>,
+    user_id: Arc<Mutex<Option<String>>>,
+) {
+    let rt = tokio::runtime::Runtime::new().unwrap();
+
+    rt.block_on(async {
+        loop {
+            let token = auth_token.lock().unwrap().clone();
+            let user_id = user_id.lock().unwrap().clone();
+
+            if let (Some(token), Some(user_id)) = (token, user_id) {
+                match music_manager
+                    .refresh_outdated_albums(&app_handle, &token, &user_id)
+                    .await
+                {
+                    Ok(refreshed) => {
+                        for refresh in refreshed {
+                            app_handle.emit("album-refreshed", refresh).unwrap();
+                        }
+                    }
+                    Err(e) => eprintln!("Error refreshing albums: {}", e),
+                }
+            }
+
+            tokio::time::sleep(ALBUM_REFRESH_INTERVAL).await;
+        }
+    });
+}
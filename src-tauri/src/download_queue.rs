@@ -1,8 +1,20 @@
+use crate::integrity::{hash_file, FileDigest};
 use crate::jellyfin::errors::JellyfinError;
-use crate::models::NewTrack;
-use crate::music_manager::MusicManager;
-use std::sync::{mpsc, Arc, Mutex};
+use crate::jellyfin::models::{ticks_to_seconds, DownloadQuality, JellyfinItem};
+use crate::models::{NewTrack, JOB_FAILED};
+use crate::music_manager::{to_json_list, MusicManager};
+use crate::rate_limit::BandwidthLimiter;
+use crate::settings::DownloadSettings;
+use chrono::Local;
+use futures::stream::{self, FuturesUnordered, StreamExt, TryStreamExt};
+use reqwest::StatusCode;
+use std::collections::{HashMap, VecDeque};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 use tauri::{AppHandle, Emitter};
+use tokio::sync::mpsc;
 
 #[derive(Clone, serde::Serialize)]
 struct DownloadQueueEmpty;
@@ -20,38 +32,450 @@ struct AlbumDownloadCompleted {
     album_id: String,
 }
 
+#[derive(Clone, serde::Serialize)]
+struct AlbumDownloadCancelled {
+    album_id: String,
+}
+
+fn emit_cancelled(app_handle: &AppHandle, album_id: &str) {
+    app_handle
+        .emit(
+            "album-download-cancelled",
+            AlbumDownloadCancelled {
+                album_id: album_id.to_string(),
+            },
+        )
+        .unwrap();
+}
+
+#[derive(Clone, serde::Serialize)]
+struct DownloadProgress {
+    album_id: String,
+    track_index: usize,
+    total_tracks: usize,
+    completed_tracks: usize,
+    bytes_received: u64,
+    bytes_total: Option<u64>,
+    bytes_per_second: u64,
+    eta_seconds: Option<u64>,
+}
+
+// how often progress is sent to the frontend while tracks download
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(250);
+
+struct TrackProgress {
+    ticks: i64,
+    bytes: u64,
+    total: Option<u64>,
+}
+
+impl TrackProgress {
+    fn fraction(&self) -> f64 {
+        match self.total {
+            Some(total) if total > 0 => self.bytes as f64 / total as f64,
+            _ => 0.0,
+        }
+    }
+}
+
+// Progress through an album, reported as "download-progress" events. File sizes
+// aren't known until each track starts, so the album ETA weighs tracks by their
+// run time instead. Several tracks can be downloading at once.
+struct AlbumProgress {
+    album_id: String,
+    total_tracks: usize,
+    total_ticks: i64,
+    completed_tracks: usize,
+    completed_ticks: i64,
+    completed_bytes: u64,
+    active: HashMap<usize, TrackProgress>,
+    started_at: Instant,
+    last_emitted: Option<Instant>,
+}
+
+impl AlbumProgress {
+    fn new(album_id: &str, tracks: &[&JellyfinItem]) -> Self {
+        Self {
+            album_id: album_id.to_string(),
+            total_tracks: tracks.len(),
+            total_ticks: tracks.iter().filter_map(|t| t.run_time_ticks).sum(),
+            completed_tracks: 0,
+            completed_ticks: 0,
+            completed_bytes: 0,
+            active: HashMap::new(),
+            started_at: Instant::now(),
+            last_emitted: None,
+        }
+    }
+
+    // tracks finished in an earlier run don't count towards the transfer
+    fn skip_track(&mut self, track: &JellyfinItem) {
+        self.total_ticks -= track.run_time_ticks.unwrap_or(0);
+        self.completed_tracks += 1;
+    }
+
+    fn start_track(&mut self, track_index: usize, track: &JellyfinItem) {
+        self.active.insert(
+            track_index,
+            TrackProgress {
+                ticks: track.run_time_ticks.unwrap_or(0),
+                bytes: 0,
+                total: None,
+            },
+        );
+    }
+
+    // called for every chunk, only emits every PROGRESS_INTERVAL
+    fn update(
+        &mut self,
+        app_handle: &AppHandle,
+        track_index: usize,
+        bytes_received: u64,
+        bytes_total: Option<u64>,
+    ) {
+        if let Some(track) = self.active.get_mut(&track_index) {
+            track.bytes = bytes_received;
+            track.total = bytes_total;
+        }
+
+        let throttled = self
+            .last_emitted
+            .is_some_and(|last| last.elapsed() < PROGRESS_INTERVAL);
+
+        if !throttled {
+            self.emit(app_handle, track_index);
+        }
+    }
+
+    fn finish_track(&mut self, app_handle: &AppHandle, track_index: usize) {
+        if let Some(track) = self.active.get_mut(&track_index) {
+            track.total = Some(track.bytes);
+        }
+
+        self.emit(app_handle, track_index);
+
+        if let Some(track) = self.active.remove(&track_index) {
+            self.completed_tracks += 1;
+            self.completed_ticks += track.ticks;
+            self.completed_bytes += track.bytes;
+        }
+    }
+
+    fn emit(&mut self, app_handle: &AppHandle, track_index: usize) {
+        self.last_emitted = Some(Instant::now());
+
+        let elapsed = self.started_at.elapsed().as_secs_f64();
+        let downloaded = self.completed_bytes + self.active.values().map(|t| t.bytes).sum::<u64>();
+
+        let bytes_per_second = if elapsed > 0.0 {
+            (downloaded as f64 / elapsed) as u64
+        } else {
+            0
+        };
+
+        // how much of the album is done, by run time if we have it
+        let album_fraction = if self.total_ticks > 0 {
+            let active_ticks = self
+                .active
+                .values()
+                .map(|t| t.ticks as f64 * t.fraction())
+                .sum::<f64>();
+
+            (self.completed_ticks as f64 + active_ticks) / self.total_ticks as f64
+        } else {
+            let active_tracks = self.active.values().map(|t| t.fraction()).sum::<f64>();
+
+            (self.completed_tracks as f64 + active_tracks) / self.total_tracks.max(1) as f64
+        };
+
+        let eta_seconds = (album_fraction > 0.0)
+            .then(|| (elapsed * (1.0 - album_fraction) / album_fraction) as u64);
+
+        let track = self.active.get(&track_index);
+
+        app_handle
+            .emit(
+                "download-progress",
+                DownloadProgress {
+                    album_id: self.album_id.clone(),
+                    track_index,
+                    total_tracks: self.total_tracks,
+                    completed_tracks: self.completed_tracks,
+                    bytes_received: track.map_or(0, |t| t.bytes),
+                    bytes_total: track.and_then(|t| t.total),
+                    bytes_per_second,
+                    eta_seconds,
+                },
+            )
+            .unwrap();
+    }
+}
+
 // Track details for a download
 pub struct Album {
     pub album_id: String,
     pub user_id: String,
+    pub quality: DownloadQuality,
+    // only these tracks, or the whole album if not set
+    pub track_ids: Option<Vec<String>>,
 }
 
-// Message type for the download queue channel
+impl Album {
+    fn wants(&self, track_id: &str) -> bool {
+        self.track_ids
+            .as_ref()
+            .is_none_or(|track_ids| track_ids.iter().any(|id| id == track_id))
+    }
+}
+
+// Message type for the download queue channel. Albums wait in the queue itself,
+// so they can be reordered, and the channel only wakes the processor
 pub enum DownloadQueueMessage {
-    NewAlbum(Album),
+    AlbumQueued,
     Shutdown,
 }
 
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum DownloadJobState {
+    Active,
+    Queued,
+    Failed,
+    Done,
+}
+
+#[derive(serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DownloadQueueItem {
+    pub album_id: String,
+    pub title: Option<String>,
+    pub artist: Option<String>,
+    pub state: DownloadJobState,
+    pub error: Option<String>,
+    pub attempts: i32,
+}
+
+#[derive(serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DownloadQueueResponse {
+    pub paused: bool,
+    pub items: Vec<DownloadQueueItem>,
+}
+
 #[derive(Clone)]
 pub struct DownloadQueue {
-    sender: mpsc::Sender<DownloadQueueMessage>,
+    sender: mpsc::UnboundedSender<DownloadQueueMessage>,
+    // albums waiting to start, in the order they'll be downloaded
+    pending: Arc<Mutex<VecDeque<Album>>>,
+    // albums being downloaded, and the flags their stream loops check
+    active: Arc<Mutex<HashMap<String, Arc<AtomicBool>>>>,
+    // albums that failed and are waiting to be tried again, and when
+    retrying: Arc<Mutex<HashMap<String, Instant>>>,
+    // no new albums start while paused, the ones downloading carry on
+    paused: Arc<AtomicBool>,
+    limiter: Arc<BandwidthLimiter>,
 }
 
 impl DownloadQueue {
     // Create a new queue and return it along with the receiver end of the channel
-    pub fn new() -> (Self, mpsc::Receiver<DownloadQueueMessage>) {
-        let (sender, receiver) = mpsc::channel();
-        (Self { sender }, receiver)
+    pub fn new() -> (Self, mpsc::UnboundedReceiver<DownloadQueueMessage>) {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        (
+            Self {
+                sender,
+                pending: Arc::new(Mutex::new(VecDeque::new())),
+                active: Arc::new(Mutex::new(HashMap::new())),
+                retrying: Arc::new(Mutex::new(HashMap::new())),
+                paused: Arc::new(AtomicBool::new(false)),
+                limiter: Arc::new(BandwidthLimiter::new(None)),
+            },
+            receiver,
+        )
     }
 
+    // albums already waiting or downloading are ignored
     pub fn add_album(&self, album: Album, app_handle: &AppHandle) {
+        {
+            let mut pending = self.pending.lock().unwrap();
+
+            if self.is_active(&album.album_id)
+                || pending
+                    .iter()
+                    .any(|queued| queued.album_id == album.album_id)
+            {
+                return;
+            }
+
+            pending.push_back(album);
+        }
+
         app_handle
             .emit("download-queue-not-empty", DownloadQueueNotEmpty)
             .unwrap();
 
-        self.sender
-            .send(DownloadQueueMessage::NewAlbum(album))
-            .unwrap();
+        self.sender.send(DownloadQueueMessage::AlbumQueued).unwrap();
+    }
+
+    // queued albums are dropped right away. an album that's downloading stops at
+    // the next chunk, and the processor cleans it up
+    pub fn cancel(&self, album_id: &str, app_handle: &AppHandle) {
+        let was_pending = {
+            let mut pending = self.pending.lock().unwrap();
+            let before = pending.len();
+            pending.retain(|album| album.album_id != album_id);
+            pending.len() < before
+        };
+
+        let was_retrying = self.retrying.lock().unwrap().remove(album_id).is_some();
+
+        if was_pending || was_retrying {
+            emit_cancelled(app_handle, album_id);
+        }
+
+        if let Some(cancelled) = self.active.lock().unwrap().get(album_id) {
+            cancelled.store(true, Ordering::Relaxed);
+        }
+
+        self.emit_if_idle(app_handle);
+    }
+
+    pub fn cancel_all(&self, app_handle: &AppHandle) {
+        let pending = self.pending.lock().unwrap().drain(..).collect::<Vec<_>>();
+
+        for album in pending {
+            emit_cancelled(app_handle, &album.album_id);
+        }
+
+        let retrying = self.retrying.lock().unwrap().drain().collect::<Vec<_>>();
+
+        for (album_id, _) in retrying {
+            emit_cancelled(app_handle, &album_id);
+        }
+
+        for cancelled in self.active.lock().unwrap().values() {
+            cancelled.store(true, Ordering::Relaxed);
+        }
+
+        self.emit_if_idle(app_handle);
+    }
+
+    // the processor only reports an empty queue when a download ends, so
+    // dropping albums that never started has to report it here
+    fn emit_if_idle(&self, app_handle: &AppHandle) {
+        if self.is_idle() {
+            app_handle
+                .emit("download-queue-empty", DownloadQueueEmpty)
+                .unwrap();
+        }
+    }
+
+    // changes which tracks a waiting album downloads
+    pub fn set_tracks(&self, album_id: &str, track_ids: Option<Vec<String>>) {
+        let mut pending = self.pending.lock().unwrap();
+
+        if let Some(album) = pending.iter_mut().find(|album| album.album_id == album_id) {
+            album.track_ids = track_ids;
+        }
+    }
+
+    // false if the album isn't waiting in the queue
+    pub fn move_to_front(&self, album_id: &str) -> bool {
+        let mut pending = self.pending.lock().unwrap();
+
+        match pending.iter().position(|album| album.album_id == album_id) {
+            Some(index) => {
+                let album = pending.remove(index).unwrap();
+                pending.push_front(album);
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn pause(&self) {
+        self.paused.store(true, Ordering::Relaxed);
+    }
+
+    pub fn resume(&self) {
+        self.paused.store(false, Ordering::Relaxed);
+        self.sender.send(DownloadQueueMessage::AlbumQueued).unwrap();
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused.load(Ordering::Relaxed)
+    }
+
+    pub fn is_active(&self, album_id: &str) -> bool {
+        self.active.lock().unwrap().contains_key(album_id)
+    }
+
+    fn is_idle(&self) -> bool {
+        self.pending.lock().unwrap().is_empty()
+            && self.active.lock().unwrap().is_empty()
+            && self.retrying.lock().unwrap().is_empty()
+    }
+
+    // the processor queues the album again once the delay is up
+    fn retry_later(&self, album_id: &str, delay: Duration) {
+        self.retrying
+            .lock()
+            .unwrap()
+            .insert(album_id.to_string(), Instant::now() + delay);
+    }
+
+    // takes the albums whose delay is up off the retry list
+    fn take_due_retries(&self) -> Vec<String> {
+        let now = Instant::now();
+        let mut retrying = self.retrying.lock().unwrap();
+
+        let due = retrying
+            .iter()
+            .filter(|(_, retry_at)| **retry_at <= now)
+            .map(|(album_id, _)| album_id.clone())
+            .collect::<Vec<_>>();
+
+        for album_id in &due {
+            retrying.remove(album_id);
+        }
+
+        due
+    }
+
+    // how long until the next retry is due, if any are waiting
+    fn next_retry_in(&self) -> Option<Duration> {
+        self.retrying
+            .lock()
+            .unwrap()
+            .values()
+            .min()
+            .map(|retry_at| retry_at.saturating_duration_since(Instant::now()))
+    }
+
+    // takes the next album off the queue and marks it active in one step, so it
+    // can't be queued again in between
+    fn start_next(&self) -> Option<(Album, Arc<AtomicBool>)> {
+        let mut pending = self.pending.lock().unwrap();
+        let album = pending.pop_front()?;
+
+        let cancelled = Arc::new(AtomicBool::new(false));
+        self.active
+            .lock()
+            .unwrap()
+            .insert(album.album_id.clone(), cancelled.clone());
+
+        Some((album, cancelled))
+    }
+
+    fn finish(&self, album_id: &str) {
+        self.active.lock().unwrap().remove(album_id);
+    }
+
+    pub fn limiter(&self) -> &BandwidthLimiter {
+        &self.limiter
+    }
+
+    pub fn set_bandwidth_limit(&self, max_bytes_per_second: Option<u64>) {
+        self.limiter.set_limit(max_bytes_per_second);
     }
 
     // Method to send a shutdown signal
@@ -60,181 +484,565 @@ impl DownloadQueue {
     }
 }
 
-fn handle_message(
-    message: DownloadQueueMessage,
+// why a download failed, sent with the "album-download-failed" event
+#[derive(Clone, Copy, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+enum DownloadFailureReason {
+    Network,
+    Server,
+    Unauthorized,
+    NotFound,
+    InsufficientSpace,
+    Other,
+}
+
+impl DownloadFailureReason {
+    fn from_error(error: &JellyfinError) -> Self {
+        match error {
+            JellyfinError::HttpRequest(_) | JellyfinError::IncompleteDownload { .. } => {
+                DownloadFailureReason::Network
+            }
+            JellyfinError::InsufficientSpace { .. } => DownloadFailureReason::InsufficientSpace,
+            JellyfinError::ApiError { status, .. } => match *status {
+                StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => {
+                    DownloadFailureReason::Unauthorized
+                }
+                StatusCode::NOT_FOUND => DownloadFailureReason::NotFound,
+                status if status.is_server_error() => DownloadFailureReason::Server,
+                _ => DownloadFailureReason::Other,
+            },
+            _ => DownloadFailureReason::Other,
+        }
+    }
+
+    // only problems that might go away on their own are retried automatically
+    fn is_retryable(self) -> bool {
+        matches!(
+            self,
+            DownloadFailureReason::Network | DownloadFailureReason::Server
+        )
+    }
+}
+
+#[derive(Clone, serde::Serialize)]
+struct AlbumDownloadFailed {
+    album_id: String,
+    reason: DownloadFailureReason,
+    message: String,
+    will_retry: bool,
+}
+
+const MAX_ATTEMPTS: i32 = 5;
+const RETRY_BASE_DELAY: Duration = Duration::from_secs(30);
+
+// retries transient failures with exponential backoff, anything else (or a job
+// out of attempts) is marked failed until the user retries it
+fn handle_failure(
     app_handle: &AppHandle,
-    rt: &tokio::runtime::Runtime,
     music_manager: &Arc<MusicManager>,
+    download_queue: &DownloadQueue,
+    album_id: &str,
+    error: &JellyfinError,
+) {
+    let reason = DownloadFailureReason::from_error(error);
+    let message = error.to_string();
+
+    eprintln!("Error downloading album {}: {}", album_id, &message);
+
+    // a job that's gone was cancelled, so there's nothing to retry
+    let attempts = music_manager
+        .repository
+        .increment_download_job_attempts(album_id)
+        .unwrap_or(MAX_ATTEMPTS);
+
+    let will_retry = reason.is_retryable() && attempts < MAX_ATTEMPTS;
+
+    if will_retry {
+        let delay = RETRY_BASE_DELAY * 2u32.pow(attempts.saturating_sub(1) as u32);
+        download_queue.retry_later(album_id, delay);
+    } else if let Err(e) =
+        music_manager
+            .repository
+            .set_download_job_status(album_id, JOB_FAILED, Some(&message))
+    {
+        eprintln!("Error failing job for album {}: {}", album_id, e);
+    }
+
+    app_handle
+        .emit(
+            "album-download-failed",
+            AlbumDownloadFailed {
+                album_id: album_id.to_string(),
+                reason,
+                message,
+                will_retry,
+            },
+        )
+        .unwrap();
+}
+
+// downloads one album from the queue, and reports how it went
+async fn process_album(
+    album: Album,
+    cancelled: Arc<AtomicBool>,
+    app_handle: &AppHandle,
+    music_manager: &Arc<MusicManager>,
+    download_queue: &DownloadQueue,
     auth_token: &Arc<Mutex<Option<String>>>,
-) -> bool {
-    // returns false if shutdown
-    match message {
-        DownloadQueueMessage::NewAlbum(album) => {
-            let token = {
-                let token_guard = auth_token.lock().unwrap();
-                token_guard.clone()
+    download_settings: &Arc<Mutex<DownloadSettings>>,
+) {
+    let token = auth_token.lock().unwrap().clone();
+
+    let token = match token {
+        Some(token) => token,
+        None => {
+            let error = JellyfinError::ApiError {
+                status: StatusCode::UNAUTHORIZED,
+                message: "No auth token available.".to_string(),
             };
 
-            if let Some(token) = token {
-                app_handle
-                    .emit(
-                        "album-download-started",
-                        AlbumDownloadStarted {
-                            album_id: album.album_id.clone(),
-                        },
-                    )
-                    .unwrap();
+            download_queue.finish(&album.album_id);
+            handle_failure(
+                app_handle,
+                music_manager,
+                download_queue,
+                &album.album_id,
+                &error,
+            );
+            return;
+        }
+    };
 
-                rt.block_on(async {
-                    let album_download_result: Result<(), JellyfinError> = async {
-                        let local_album = music_manager
-                            .sync_album(&album.album_id, &token, Some(&album.user_id))
-                            .await?;
+    app_handle
+        .emit(
+            "album-download-started",
+            AlbumDownloadStarted {
+                album_id: album.album_id.clone(),
+            },
+        )
+        .unwrap();
 
-                        // already downloaded
-                        if local_album.path.is_some() {
-                            return Ok(());
-                        }
+    let settings = download_settings.lock().unwrap().clone();
+    let mut album_dir = None;
 
-                        // create the album directory
-                        let dir = music_manager.create_album_dir(
-                            app_handle,
-                            &local_album.artist,
-                            &local_album.title,
-                        )?;
-
-                        let image_path = local_album
-                            .image_id
-                            .as_ref()
-                            .map(|_| dir.join("cover.jpg").to_string_lossy().to_string());
-
-                        // get the album art if we have it
-                        if let Some(image_path) = &image_path {
-                            music_manager
-                                .download_album_art(
-                                    &local_album.jellyfin_id,
-                                    &local_album.image_id.as_ref().unwrap(),
-                                    &image_path,
-                                    &token,
-                                )
-                                .await?;
-                        }
+    let album_download_result = download_album(
+        &album,
+        &token,
+        app_handle,
+        music_manager,
+        &cancelled,
+        &download_queue.limiter,
+        &settings,
+        &mut album_dir,
+    )
+    .await;
 
-                        // get the tracks for the album
-                        let tracks = music_manager.get_tracks(&album.album_id, &token).await?;
-                        let total_tracks = tracks.items.len();
-
-                        for track in tracks.items {
-                            let track_filename =
-                                music_manager.generate_track_name(&track, total_tracks);
-                            let download_path = dir.join(&track_filename);
-
-                            music_manager
-                                .repository
-                                .insert_track(&NewTrack {
-                                    jellyfin_id: &track.id,
-                                    name: &track.name,
-                                    album_id: local_album.id,
-                                    path: Some(download_path.to_string_lossy().to_string()),
-                                    track_index: track.index_number.unwrap_or(0) as i32,
-                                })
-                                .map_err(|e| JellyfinError::GenericError(e.to_string()))?;
-
-                            music_manager
-                                .download_track(&track.id, &download_path.to_string_lossy(), &token)
-                                .await?;
-                        }
+    download_queue.finish(&album.album_id);
 
-                        // mark album as downloaded
-                        music_manager
-                            .repository
-                            .mark_album_as_downloaded(
-                                &album.album_id,
-                                &dir.to_string_lossy(),
-                                image_path.as_deref(),
-                            )
-                            .map_err(|e| JellyfinError::GenericError(e.to_string()))
-                    }
-                    .await;
-
-                    if let Err(e) = album_download_result {
-                        let error_message = e.to_string();
-                        eprintln!(
-                            "Error downloading album {}: {}",
-                            &album.album_id, &error_message
-                        );
-                    } else {
-                        app_handle
-                            .emit(
-                                "album-download-completed",
-                                AlbumDownloadCompleted {
-                                    album_id: album.album_id.clone(),
-                                },
-                            )
-                            .unwrap();
-                    }
+    match album_download_result {
+        Err(JellyfinError::Cancelled) => {
+            if let Err(e) =
+                music_manager.discard_partial_album(&album.album_id, album_dir.as_deref())
+            {
+                eprintln!("Error cleaning up album {}: {}", &album.album_id, e);
+            }
+
+            emit_cancelled(app_handle, &album.album_id);
+        }
+        Err(e) => handle_failure(
+            app_handle,
+            music_manager,
+            download_queue,
+            &album.album_id,
+            &e,
+        ),
+        Ok(()) => {
+            app_handle
+                .emit(
+                    "album-download-completed",
+                    AlbumDownloadCompleted {
+                        album_id: album.album_id.clone(),
+                    },
+                )
+                .unwrap();
+        }
+    }
+}
+
+// roughly how much space the album's tracks will take. originals use the file
+// sizes jellyfin reports, transcodes their bitrate and run time
+fn estimate_album_size(tracks: &[&JellyfinItem], quality: &DownloadQuality) -> u64 {
+    tracks
+        .iter()
+        .map(|track| match quality.bitrate() {
+            Some(bitrate) => track.run_time_ticks.map_or(0, |ticks| {
+                (ticks_to_seconds(ticks) * bitrate as f64 / 8.0) as u64
+            }),
+            None => track.file_size().unwrap_or(0),
+        })
+        .sum()
+}
+
+// album_dir is set as soon as the directory exists, so a cancelled download
+// can be cleaned up
+#[allow(clippy::too_many_arguments)]
+async fn download_album(
+    album: &Album,
+    token: &str,
+    app_handle: &AppHandle,
+    music_manager: &Arc<MusicManager>,
+    cancelled: &AtomicBool,
+    limiter: &BandwidthLimiter,
+    settings: &DownloadSettings,
+    album_dir: &mut Option<PathBuf>,
+) -> Result<(), JellyfinError> {
+    let local_album = music_manager
+        .sync_album(&album.album_id, token, Some(&album.user_id))
+        .await?;
+
+    // get the tracks for the album
+    let tracks = music_manager
+        .get_tracks(&album.album_id, token, Some(&album.user_id), true)
+        .await?;
+    let total_tracks = tracks.items.len();
+    let multi_disc = tracks
+        .items
+        .iter()
+        .any(|track| track.parent_index_number.unwrap_or(1) > 1);
+
+    // tracks an earlier download already has
+    let local_tracks = music_manager
+        .repository
+        .get_album_details(&album.album_id)
+        .map_err(|e| JellyfinError::GenericError(e.to_string()))?
+        .map(|(_, tracks)| tracks)
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|track| track.path.is_some())
+        .map(|track| (track.jellyfin_id.clone(), track))
+        .collect::<HashMap<_, _>>();
+
+    let wanted_tracks = tracks
+        .items
+        .iter()
+        .filter(|track| album.wants(&track.id))
+        .collect::<Vec<_>>();
+
+    // already downloaded. tracks asked for by id are looked at again, since
+    // that's how damaged ones get repaired
+    if local_album.path.is_some()
+        && album.track_ids.is_none()
+        && wanted_tracks
+            .iter()
+            .all(|track| local_tracks.contains_key(&track.id))
+    {
+        return music_manager.finish_download_job(&album.album_id);
+    }
+
+    // create the album directory
+    let dir =
+        music_manager.create_album_dir(app_handle, &local_album.artist, &local_album.title)?;
+    *album_dir = Some(dir.clone());
+
+    // get whatever album art the server has
+    let artwork = music_manager
+        .download_album_artwork(&local_album.jellyfin_id, &dir, token)
+        .await?;
+
+    // what earlier attempts already got of the wanted tracks counts, but not
+    // the rest of the directory, like tracks a previous download kept. files
+    // of tracks already in the library are only wanted again to be repaired
+    let resumed: u64 = wanted_tracks
+        .iter()
+        .filter_map(|track| {
+            let track_filename =
+                music_manager.generate_track_name(track, total_tracks, multi_disc, &album.quality);
+            let download_path = dir.join(&track_filename);
+
+            let finished = if local_tracks.contains_key(&track.id) {
+                None
+            } else {
+                std::fs::metadata(&download_path).ok()
+            };
+
+            finished.or_else(|| {
+                std::fs::metadata(format!("{}.part", download_path.to_string_lossy())).ok()
+            })
+        })
+        .map(|metadata| metadata.len())
+        .sum();
+
+    let required = estimate_album_size(&wanted_tracks, &album.quality).saturating_sub(resumed);
+
+    music_manager
+        .ensure_space_for_album(app_handle, &dir, required, settings.library_quota_bytes)
+        .await?;
+
+    let progress = Mutex::new(AlbumProgress::new(&album.album_id, &wanted_tracks));
+    let progress = &progress;
+    let local_tracks = &local_tracks;
+    let dir = &dir;
+    let local_album_id = local_album.id;
+
+    let new_tracks = stream::iter(tracks.items.iter().enumerate())
+        .map(|(track_index, track)| async move {
+            if cancelled.load(Ordering::Relaxed) {
+                return Err(JellyfinError::Cancelled);
+            }
+
+            let (path, size, sha256, date_modified) = if album.wants(&track.id) {
+                let track_filename = music_manager.generate_track_name(
+                    track,
+                    total_tracks,
+                    multi_disc,
+                    &album.quality,
+                );
+                let download_path = dir.join(&track_filename);
+
+                // files only get their final name once complete, so one that's
+                // already there was finished by an earlier attempt. unless it no
+                // longer matches what was saved when it downloaded, or the server
+                // has saved the track since, then it's only replaced once the
+                // new copy is in
+                let existing = hash_existing(&download_path).await?.filter(|digest| {
+                    local_tracks.get(&track.id).is_none_or(|local| {
+                        local.size.is_none_or(|size| size as u64 == digest.size)
+                            && local
+                                .sha256
+                                .as_deref()
+                                .is_none_or(|sha256| sha256 == digest.sha256)
+                            && local
+                                .date_modified
+                                .zip(track.date_last_saved)
+                                .is_none_or(|(local, server)| server.naive_utc() <= local)
+                    })
                 });
+
+                let digest = if let Some(digest) = existing {
+                    progress.lock().unwrap().skip_track(track);
+                    digest
+                } else {
+                    progress.lock().unwrap().start_track(track_index, track);
+
+                    let digest = music_manager
+                        .download_track(
+                            &track.id,
+                            &download_path.to_string_lossy(),
+                            token,
+                            Some(&album.user_id),
+                            &album.quality,
+                            |received, total| {
+                                progress.lock().unwrap().update(
+                                    app_handle,
+                                    track_index,
+                                    received,
+                                    total,
+                                )
+                            },
+                            cancelled,
+                            limiter,
+                        )
+                        .await?;
+
+                    progress
+                        .lock()
+                        .unwrap()
+                        .finish_track(app_handle, track_index);
+
+                    digest
+                };
+
+                // the original file should match the size jellyfin has for it. a
+                // wrong one is removed so the retry downloads it again
+                if let Some(expected) = track
+                    .file_size()
+                    .filter(|&size| album.quality.codec().is_none() && size != digest.size)
+                {
+                    let _ = std::fs::remove_file(&download_path);
+
+                    return Err(JellyfinError::IncompleteDownload {
+                        received: digest.size,
+                        expected,
+                    });
+                }
+
+                (
+                    Some(download_path.to_string_lossy().to_string()),
+                    Some(digest.size as i64),
+                    Some(digest.sha256),
+                    track.date_last_saved.map(|date| date.naive_utc()),
+                )
             } else {
-                let error_message = "Download failed: No auth token available.".to_string();
-                eprintln!("{}", &error_message);
+                // not asked for this time, but what an earlier download got is
+                // kept, along with when the server had saved it
+                match local_tracks.get(&track.id) {
+                    Some(local) => (
+                        local.path.clone(),
+                        local.size,
+                        local.sha256.clone(),
+                        local.date_modified,
+                    ),
+                    None => (None, None, None, None),
+                }
+            };
+
+            Ok(NewTrack {
+                jellyfin_id: &track.id,
+                name: &track.name,
+                album_id: local_album_id,
+                path,
+                track_index: track.index_number.unwrap_or(0) as i32,
+                disc_number: track.parent_index_number.map(|d| d as i32),
+                artists: to_json_list(track.artist_names()),
+                run_time_ticks: track.run_time_ticks,
+                is_favorite: track.is_favorite(),
+                play_count: track.play_count() as i32,
+                size,
+                sha256,
+                date_modified,
+            })
+        })
+        .buffer_unordered(settings.track_concurrency.max(1))
+        .try_collect::<Vec<_>>()
+        .await?;
+
+    // the album only shows up as downloaded once every wanted track is in
+    music_manager
+        .repository
+        .complete_album_download(
+            &album.album_id,
+            &dir.to_string_lossy(),
+            &artwork,
+            &album.quality.to_string(),
+            &new_tracks,
+        )
+        .map_err(|e| JellyfinError::GenericError(e.to_string()))?;
+
+    // a track downloaded again under a new name leaves its old file behind
+    for track in &new_tracks {
+        let old_path = local_tracks
+            .get(track.jellyfin_id)
+            .and_then(|local| local.path.as_deref());
+
+        if let Some(old_path) = old_path.filter(|&old_path| track.path.as_deref() != Some(old_path))
+        {
+            if let Err(e) = std::fs::remove_file(old_path) {
+                eprintln!("Failed to remove {}: {}", old_path, e);
             }
-            true
-        }
-        DownloadQueueMessage::Shutdown => {
-            println!("Download queue shutting down.");
-            false
         }
     }
+
+    Ok(())
+}
+
+// hashes a file an earlier download left, off the async threads
+async fn hash_existing(path: &Path) -> Result<Option<FileDigest>, JellyfinError> {
+    if !path.exists() {
+        return Ok(None);
+    }
+
+    let path = path.to_path_buf();
+
+    tokio::task::spawn_blocking(move || hash_file(&path))
+        .await
+        .map_err(|e| JellyfinError::GenericError(e.to_string()))?
+        .map(Some)
+        .map_err(|e| JellyfinError::GenericError(format!("Failed to hash track: {}", e)))
 }
 
-// The processor function, to be run in a thread
+const SETTINGS_CHECK_INTERVAL: Duration = Duration::from_secs(30);
+
+// The processor function, to be run in a thread. Downloads up to the configured
+// number of albums at once, taking more from the queue as they finish
 pub fn process_downloads(
     app_handle: AppHandle,
-    receiver: mpsc::Receiver<DownloadQueueMessage>,
-    jellyfin_client: Arc<MusicManager>,
+    mut receiver: mpsc::UnboundedReceiver<DownloadQueueMessage>,
+    music_manager: Arc<MusicManager>,
+    download_queue: DownloadQueue,
     auth_token: Arc<Mutex<Option<String>>>,
+    download_settings: Arc<Mutex<DownloadSettings>>,
 ) {
     let rt = tokio::runtime::Runtime::new().unwrap();
 
-    'main_loop: loop {
-        // Wait for the first message of a batch
-        let mut current_message = match receiver.recv() {
-            Ok(msg) => msg,
-            Err(_) => {
-                println!("Download queue channel disconnected.");
-                break; // Exit the main loop
-            }
-        };
+    rt.block_on(async {
+        let mut active = FuturesUnordered::new();
 
-        // Inner loop to process the batch
         loop {
-            if !handle_message(
-                current_message,
-                &app_handle,
-                &rt,
-                &jellyfin_client,
-                &auth_token,
-            ) {
-                break 'main_loop; // Shutdown received, exit completely
+            // read each time round, so changed settings apply to the next album
+            let (album_concurrency, window_open) = {
+                let settings = download_settings.lock().unwrap();
+                (
+                    settings.album_concurrency.max(1),
+                    settings.is_download_window_open(Local::now().time()),
+                )
+            };
+
+            // failed albums whose delay is up go back in the queue, unless their
+            // job was cancelled while they waited
+            let due = download_queue.take_due_retries();
+
+            for album_id in &due {
+                if let Err(e) = music_manager.requeue_download_job(&app_handle, album_id) {
+                    eprintln!("Error retrying album {}: {}", album_id, e);
+                }
+            }
+
+            if !due.is_empty() && active.is_empty() && download_queue.is_idle() {
+                app_handle
+                    .emit("download-queue-empty", DownloadQueueEmpty)
+                    .unwrap();
             }
 
-            // Try to get the next message without blocking
-            match receiver.try_recv() {
-                Ok(next_msg) => {
-                    // If we got another message, process it in the next iteration
-                    current_message = next_msg;
+            if window_open && !download_queue.is_paused() {
+                while active.len() < album_concurrency {
+                    let Some((album, cancelled)) = download_queue.start_next() else {
+                        break;
+                    };
+
+                    active.push(process_album(
+                        album,
+                        cancelled,
+                        &app_handle,
+                        &music_manager,
+                        &download_queue,
+                        &auth_token,
+                        &download_settings,
+                    ));
                 }
-                Err(mpsc::TryRecvError::Empty) => {
-                    // The queue is now empty, emit the event and wait for a new message
-                    app_handle
-                        .emit("download-queue-empty", DownloadQueueEmpty)
-                        .unwrap();
-                    break; // Exit inner loop, go back to recv()
+            }
+
+            let wait = download_queue
+                .next_retry_in()
+                .map_or(SETTINGS_CHECK_INTERVAL, |due| {
+                    due.min(SETTINGS_CHECK_INTERVAL)
+                });
+
+            tokio::select! {
+                message = receiver.recv() => {
+                    match message {
+                        Some(DownloadQueueMessage::AlbumQueued) => {}
+                        Some(DownloadQueueMessage::Shutdown) => {
+                            println!("Download queue shutting down.");
+                            break;
+                        }
+                        None => {
+                            println!("Download queue channel disconnected.");
+                            break;
+                        }
+                    }
                 }
-                Err(mpsc::TryRecvError::Disconnected) => {
-                    break 'main_loop; // Channel disconnected
+                Some(()) = active.next(), if !active.is_empty() => {
+                    // nothing downloading and nothing waiting
+                    if active.is_empty() && download_queue.is_idle() {
+                        app_handle
+                            .emit("download-queue-empty", DownloadQueueEmpty)
+                            .unwrap();
+                    }
                 }
+                // look at the settings again, in case a window opened or the
+                // limits changed while we were waiting, or a retry is due
+                _ = tokio::time::sleep(wait) => {}
             }
         }
-    }
+    });
 }
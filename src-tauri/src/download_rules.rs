@@ -0,0 +1,157 @@
+use crate::models::DownloadRule;
+use crate::music_manager::MusicManager;
+use crate::settings::DownloadSettings;
+use serde::{Deserialize, Serialize};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tauri::{AppHandle, Emitter};
+
+const RULE_SYNC_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+// what a download rule keeps offline, stored as json in download_rules
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "camelCase")]
+pub enum RuleCriteria {
+    Favorites,
+    #[serde(rename_all = "camelCase")]
+    RecentlyAdded {
+        count: u32,
+    },
+    #[serde(rename_all = "camelCase")]
+    Artists {
+        artist_ids: Vec<String>,
+    },
+    // just the playlist's tracks, not their whole albums
+    #[serde(rename_all = "camelCase")]
+    Playlist {
+        playlist_id: String,
+    },
+}
+
+impl RuleCriteria {
+    pub fn validate(&self) -> Result<(), String> {
+        match self {
+            RuleCriteria::RecentlyAdded { count: 0 } => Err("Count must be at least 1".to_string()),
+            RuleCriteria::Artists { artist_ids } if artist_ids.is_empty() => {
+                Err("No artists selected".to_string())
+            }
+            RuleCriteria::Playlist { playlist_id } if playlist_id.is_empty() => {
+                Err("No playlist selected".to_string())
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DownloadRuleResponse {
+    pub id: i32,
+    pub criteria: RuleCriteria,
+    pub remove_unmatched: bool,
+}
+
+impl DownloadRuleResponse {
+    // None for a rule whose criteria can't be read, which is skipped
+    pub fn from_rule(rule: DownloadRule) -> Option<Self> {
+        match serde_json::from_str(&rule.criteria) {
+            Ok(criteria) => Some(Self {
+                id: rule.id,
+                criteria,
+                remove_unmatched: rule.remove_unmatched,
+            }),
+            Err(e) => {
+                eprintln!("Skipping download rule {}: {}", rule.id, e);
+                None
+            }
+        }
+    }
+}
+
+// sent as "download-rules-synced", and returned when a sync is asked for
+#[derive(Clone, Serialize)]
+pub struct RuleSyncSummary {
+    pub queued: usize,
+    pub removed: Vec<String>,
+}
+
+// The rule sync loop, to be run in a thread. Checks the rules once at startup
+// and then every RULE_SYNC_INTERVAL, whenever someone is logged in.
+pub fn run_rule_sync(
+    app_handle: AppHandle,
+    music_manager: Arc<MusicManager>,
+    auth_token: Arc<Mutex<Option<String>>>,
+    user_id: Arc<Mutex<Option<String>>>,
+    download_settings: Arc<Mutex<DownloadSettings>>,
+) {
+    let rt = tokio::runtime::Runtime::new().unwrap();
+
+    rt.block_on(async {
+        loop {
+            let token = auth_token.lock().unwrap().clone();
+            let user_id = user_id.lock().unwrap().clone();
+
+            if let (Some(token), Some(user_id)) = (token, user_id) {
+                let quality = download_settings.lock().unwrap().quality;
+
+                match music_manager
+                    .sync_download_rules(&app_handle, &token, &user_id, quality)
+                    .await
+                {
+                    Ok(summary) => app_handle.emit("download-rules-synced", summary).unwrap(),
+                    Err(e) => eprintln!("Error syncing download rules: {}", e),
+                }
+            }
+
+            tokio::time::sleep(RULE_SYNC_INTERVAL).await;
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rules_that_match_something_are_valid() {
+        for criteria in [
+            RuleCriteria::Favorites,
+            RuleCriteria::RecentlyAdded { count: 1 },
+            RuleCriteria::Artists {
+                artist_ids: vec!["artist".to_string()],
+            },
+            RuleCriteria::Playlist {
+                playlist_id: "playlist".to_string(),
+            },
+        ] {
+            assert_eq!(criteria.validate(), Ok(()));
+        }
+    }
+
+    #[test]
+    fn rules_that_cant_match_anything_are_rejected() {
+        for criteria in [
+            RuleCriteria::RecentlyAdded { count: 0 },
+            RuleCriteria::Artists {
+                artist_ids: Vec::new(),
+            },
+            RuleCriteria::Playlist {
+                playlist_id: String::new(),
+            },
+        ] {
+            assert!(criteria.validate().is_err());
+        }
+    }
+
+    #[test]
+    fn criteria_are_stored_as_tagged_json() {
+        let criteria = RuleCriteria::RecentlyAdded { count: 20 };
+        let json = serde_json::to_string(&criteria).unwrap();
+
+        assert_eq!(json, r#"{"kind":"recentlyAdded","count":20}"#);
+        assert!(matches!(
+            serde_json::from_str(&json).unwrap(),
+            RuleCriteria::RecentlyAdded { count: 20 }
+        ));
+    }
+}
@@ -0,0 +1,183 @@
+use sanitize_filename::sanitize;
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+// An on-disk cache for artwork fetched from the server. The webview gets files
+// through the thumbnail protocol, which fills the cache on a miss, so the auth
+// token never ends up in a url. The cache keeps an index of its files in
+// memory, read from the directory on first use. Each file's modified time
+// doubles as its last-used time, so LRU order survives restarts.
+pub struct ImageCache {
+    dir: PathBuf,
+    max_bytes: u64,
+    index: Mutex<Option<HashMap<String, CachedImage>>>,
+    // keys being fetched, so two requests for one image don't write one file
+    fetching: Mutex<HashMap<String, Arc<tokio::sync::Mutex<()>>>>,
+}
+
+struct CachedImage {
+    path: PathBuf,
+    size: u64,
+    last_used: SystemTime,
+}
+
+impl ImageCache {
+    pub fn new(dir: PathBuf, max_bytes: u64) -> Self {
+        Self {
+            dir,
+            max_bytes,
+            index: Mutex::new(None),
+            fetching: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    // cache keys become file stems, the extension depends on the image format
+    pub fn key(item_id: &str, image_tag: &str) -> String {
+        sanitize(format!("{}-{}", item_id, image_tag))
+    }
+
+    // returns the cached file for the key and marks it as recently used
+    pub fn get(&self, key: &str) -> Option<PathBuf> {
+        self.with_index(|index| {
+            let image = index.get_mut(key)?;
+            image.last_used = SystemTime::now();
+
+            if let Ok(file) = fs::File::options().write(true).open(&image.path) {
+                let _ = file.set_modified(image.last_used);
+            }
+
+            Some(image.path.clone())
+        })
+    }
+
+    // held while the key's file is fetched. whoever waited on it should look in
+    // the cache again before fetching it themselves
+    pub async fn lock_fetch(&self, key: &str) -> tokio::sync::OwnedMutexGuard<()> {
+        let lock = self
+            .fetching
+            .lock()
+            .unwrap()
+            .entry(key.to_string())
+            .or_default()
+            .clone();
+
+        lock.lock_owned().await
+    }
+
+    // releases the key once its fetch is done. it's forgotten when nobody else
+    // is waiting on it
+    pub fn finish_fetch(&self, key: &str, guard: tokio::sync::OwnedMutexGuard<()>) {
+        let mut fetching = self.fetching.lock().unwrap();
+
+        // the map and this guard hold the only references
+        if fetching
+            .get(key)
+            .is_some_and(|lock| Arc::strong_count(lock) <= 2)
+        {
+            fetching.remove(key);
+        }
+
+        drop(guard);
+    }
+
+    pub fn ensure_dir(&self) -> io::Result<()> {
+        if !self.dir.exists() {
+            fs::create_dir_all(&self.dir)?;
+        }
+
+        Ok(())
+    }
+
+    // records a file written into the cache dir, then evicts if it's over its cap
+    pub fn insert(&self, key: &str, path: &Path) -> io::Result<()> {
+        let size = fs::metadata(path)?.len();
+
+        self.with_index(|index| {
+            index.insert(
+                key.to_string(),
+                CachedImage {
+                    path: path.to_path_buf(),
+                    size,
+                    last_used: SystemTime::now(),
+                },
+            );
+
+            self.evict(index)
+        })
+    }
+
+    // removes the least recently used files until the cache fits under its cap
+    fn evict(&self, index: &mut HashMap<String, CachedImage>) -> io::Result<()> {
+        let mut total: u64 = index.values().map(|image| image.size).sum();
+
+        if total <= self.max_bytes {
+            return Ok(());
+        }
+
+        let mut keys = index
+            .iter()
+            .map(|(key, image)| (key.clone(), image.last_used))
+            .collect::<Vec<_>>();
+
+        keys.sort_by_key(|(_, last_used)| *last_used);
+
+        for (key, _) in keys {
+            if total <= self.max_bytes {
+                break;
+            }
+
+            if let Some(image) = index.remove(&key) {
+                match fs::remove_file(&image.path) {
+                    Ok(()) => {}
+                    Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+                    Err(e) => return Err(e),
+                }
+
+                total = total.saturating_sub(image.size);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn with_index<T>(&self, f: impl FnOnce(&mut HashMap<String, CachedImage>) -> T) -> T {
+        let mut index = self.index.lock().unwrap();
+        let index = index.get_or_insert_with(|| self.read_dir());
+
+        f(index)
+    }
+
+    // a missing dir is just an empty cache
+    fn read_dir(&self) -> HashMap<String, CachedImage> {
+        let Ok(entries) = fs::read_dir(&self.dir) else {
+            return HashMap::new();
+        };
+
+        entries
+            .filter_map(|entry| entry.ok())
+            .filter_map(|entry| {
+                let path = entry.path();
+                let key = path.file_stem()?.to_str()?.to_string();
+                let metadata = entry.metadata().ok()?;
+                let last_used = metadata.modified().ok()?;
+
+                Some((
+                    key,
+                    CachedImage {
+                        path,
+                        size: metadata.len(),
+                        last_used,
+                    },
+                ))
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,224 @@
+use crate::models::Track;
+use sha2::{Digest, Sha256};
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::Path;
+
+pub struct FileDigest {
+    pub size: u64,
+    pub sha256: String,
+}
+
+// a running hash of a file as it's written, so a download doesn't have to be
+// read back from disk to be hashed
+#[derive(Default)]
+pub struct FileHasher {
+    hasher: Sha256,
+    size: u64,
+}
+
+impl FileHasher {
+    // starts from what's already in the file, like a part file being resumed
+    pub fn from_file(path: &Path) -> io::Result<Self> {
+        let mut file = File::open(path)?;
+        let mut hasher = Self::default();
+        let mut buffer = vec![0; 64 * 1024];
+
+        loop {
+            let read = file.read(&mut buffer)?;
+            if read == 0 {
+                break;
+            }
+
+            hasher.update(&buffer[..read]);
+        }
+
+        Ok(hasher)
+    }
+
+    pub fn update(&mut self, bytes: &[u8]) {
+        self.hasher.update(bytes);
+        self.size += bytes.len() as u64;
+    }
+
+    pub fn finish(self) -> FileDigest {
+        FileDigest {
+            size: self.size,
+            sha256: format!("{:x}", self.hasher.finalize()),
+        }
+    }
+}
+
+pub fn hash_file(path: &Path) -> io::Result<FileDigest> {
+    FileHasher::from_file(path).map(FileHasher::finish)
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum TrackIssue {
+    Missing,
+    // not the size it was downloaded at, usually cut short
+    Truncated,
+    // the right size, but the contents changed
+    Corrupt,
+}
+
+pub enum TrackCheck {
+    Intact(FileDigest),
+    Damaged(TrackIssue),
+}
+
+// compares a track's file against the size and hash saved when it downloaded.
+// tracks downloaded before hashes were saved only have their size checked if
+// it's known, and the digest is returned so it can be saved now
+pub fn check_track(track: &Track) -> io::Result<TrackCheck> {
+    let path = match track.path.as_deref().map(Path::new) {
+        Some(path) if path.is_file() => path,
+        _ => return Ok(TrackCheck::Damaged(TrackIssue::Missing)),
+    };
+
+    let size = path.metadata()?.len();
+
+    // no need to hash a file that's the wrong size
+    if track.size.is_some_and(|expected| expected as u64 != size) {
+        return Ok(TrackCheck::Damaged(TrackIssue::Truncated));
+    }
+
+    let digest = hash_file(path)?;
+
+    match track.sha256.as_deref() {
+        Some(expected) if expected != digest.sha256 => Ok(TrackCheck::Damaged(TrackIssue::Corrupt)),
+        _ => Ok(TrackCheck::Intact(digest)),
+    }
+}
+
+#[derive(serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DamagedTrack {
+    pub album_id: String,
+    pub album_title: String,
+    pub track_id: String,
+    pub track_name: String,
+    pub path: Option<String>,
+    pub issue: TrackIssue,
+}
+
+#[derive(serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LibraryVerification {
+    pub checked_tracks: usize,
+    pub damaged_tracks: Vec<DamagedTrack>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::path::PathBuf;
+
+    fn write_temp_file(contents: &[u8]) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("track-{}.flac", uuid::Uuid::new_v4()));
+        fs::write(&path, contents).unwrap();
+        path
+    }
+
+    fn track(path: Option<&Path>, size: Option<i64>, sha256: Option<String>) -> Track {
+        Track {
+            id: 1,
+            jellyfin_id: "track".to_string(),
+            name: "Track".to_string(),
+            album_id: 1,
+            path: path.map(|path| path.to_string_lossy().to_string()),
+            track_index: Some(1),
+            disc_number: None,
+            artists: None,
+            run_time_ticks: None,
+            is_favorite: false,
+            play_count: 0,
+            size,
+            sha256,
+            date_modified: None,
+        }
+    }
+
+    fn issue(check: TrackCheck) -> Option<TrackIssue> {
+        match check {
+            TrackCheck::Intact(_) => None,
+            TrackCheck::Damaged(issue) => Some(issue),
+        }
+    }
+
+    #[test]
+    fn track_without_a_file_is_missing() {
+        let path = std::env::temp_dir().join("no-such-track.flac");
+
+        assert_eq!(
+            issue(check_track(&track(Some(&path), None, None)).unwrap()),
+            Some(TrackIssue::Missing)
+        );
+        assert_eq!(
+            issue(check_track(&track(None, None, None)).unwrap()),
+            Some(TrackIssue::Missing)
+        );
+    }
+
+    #[test]
+    fn track_of_the_wrong_size_is_truncated() {
+        let path = write_temp_file(b"cut short");
+        let expected = hash_file(&path).unwrap().sha256;
+
+        let check = check_track(&track(Some(&path), Some(100), Some(expected)));
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(issue(check.unwrap()), Some(TrackIssue::Truncated));
+    }
+
+    #[test]
+    fn track_of_the_right_size_with_a_different_hash_is_corrupt() {
+        let path = write_temp_file(b"original");
+        let expected = hash_file(&path).unwrap();
+        fs::write(&path, b"garbled!").unwrap();
+
+        let check = check_track(&track(
+            Some(&path),
+            Some(expected.size as i64),
+            Some(expected.sha256),
+        ));
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(issue(check.unwrap()), Some(TrackIssue::Corrupt));
+    }
+
+    #[test]
+    fn track_matching_its_size_and_hash_is_intact() {
+        let path = write_temp_file(b"original");
+        let expected = hash_file(&path).unwrap();
+
+        let check = check_track(&track(
+            Some(&path),
+            Some(expected.size as i64),
+            Some(expected.sha256.clone()),
+        ));
+        fs::remove_file(&path).unwrap();
+
+        match check.unwrap() {
+            TrackCheck::Intact(digest) => assert_eq!(digest.sha256, expected.sha256),
+            TrackCheck::Damaged(issue) => panic!("expected intact, got {:?}", issue),
+        }
+    }
+
+    #[test]
+    fn hashing_in_chunks_matches_hashing_the_file() {
+        let path = write_temp_file(b"first half, second half");
+
+        let mut hasher = FileHasher::default();
+        hasher.update(b"first half, ");
+        hasher.update(b"second half");
+        let streamed = hasher.finish();
+        let read = hash_file(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(streamed.size, read.size);
+        assert_eq!(streamed.sha256, read.sha256);
+    }
+}
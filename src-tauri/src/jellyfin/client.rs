@@ -1,10 +1,30 @@
+use crate::integrity::{FileDigest, FileHasher};
 use crate::jellyfin::errors::JellyfinError;
-use crate::jellyfin::models::{AuthRequest, AuthResponse, JellyfinItem, JellyfinItemsResponse};
+use crate::jellyfin::models::{
+    AuthRequest, AuthResponse, ClientCapabilities, DownloadQuality, JellyfinItem,
+    JellyfinItemsResponse, PublicSystemInfo, UserDetails,
+};
+use crate::rate_limit::BandwidthLimiter;
+use crate::response_cache::ResponseCache;
+use chrono::NaiveDateTime;
 use futures::StreamExt;
+use reqwest::header::{ETAG, IF_NONE_MATCH, RANGE};
 use reqwest::{Client, StatusCode};
+use serde::de::DeserializeOwned;
+use sha2::{Digest, Sha256};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
 use tokio::io::AsyncWriteExt;
 use url::Url;
 
+// fields jellyfin leaves out of item responses unless asked for
+const ITEM_FIELDS: &str = "Genres,Overview,DateCreated";
+// tracks also need their file sizes, to check downloads against, and when they
+// were last saved, to tell when a downloaded one changed
+const TRACK_FIELDS: &str = "Genres,Overview,DateCreated,MediaSources,DateLastSaved";
+const ITEM_IDS_PER_REQUEST: usize = 100;
+const CHANGED_ITEMS_PAGE_SIZE: usize = 500;
+
 pub struct JellyfinClient {
     base_url: String,
     http_client: Client,
@@ -12,6 +32,7 @@ pub struct JellyfinClient {
     device_name: String,
     device_id: String,
     app_version: String,
+    response_cache: ResponseCache,
 }
 
 impl JellyfinClient {
@@ -21,6 +42,7 @@ impl JellyfinClient {
         device_name: String,
         device_id: String,
         app_version: String,
+        response_cache: ResponseCache,
     ) -> Self {
         Self {
             base_url,
@@ -29,9 +51,108 @@ impl JellyfinClient {
             device_name,
             device_id,
             app_version,
+            response_cache,
         }
     }
 
+    // cached responses get revalidated next time they're used, for when the
+    // server tells us something changed
+    pub fn expire_cached_responses(&self) -> Result<(), JellyfinError> {
+        self.response_cache.expire_all()
+    }
+
+    // GETs json through the response cache. returns the parsed body and whether
+    // it's a stale copy, served because the server couldn't be reached.
+    // revalidate skips the ttl, for callers that act on the response (downloads,
+    // refreshes) rather than just showing it
+    async fn get_cached<T: DeserializeOwned>(
+        &self,
+        url: Url,
+        access_token: &str,
+        revalidate: bool,
+    ) -> Result<(T, bool), JellyfinError> {
+        // responses include user data, so they're kept apart per user. the token
+        // is hashed so it doesn't end up in the database
+        let key = format!("{:x}:{}", Sha256::digest(access_token.as_bytes()), url);
+
+        // a broken cache shouldn't stop us from talking to the server
+        let cached = self.response_cache.get(&key).unwrap_or_else(|e| {
+            eprintln!("Error reading response cache: {}", e);
+            None
+        });
+
+        if let Some(entry) = cached
+            .as_ref()
+            .filter(|e| !revalidate && self.response_cache.is_fresh(e))
+        {
+            return Ok((serde_json::from_str(&entry.body)?, false));
+        }
+
+        let mut request = self.http_client.get(url.as_str()).header(
+            "Authorization",
+            format!(
+                "MediaBrowser Token=\"{}\", Client=\"{}\", Device=\"{}\", DeviceId=\"{}\", Version=\"{}\"",
+                access_token, self.app_name, self.device_name, self.device_id, self.app_version
+            ),
+        );
+
+        if let Some(etag) = cached.as_ref().and_then(|e| e.etag.as_deref()) {
+            request = request.header(IF_NONE_MATCH, etag);
+        }
+
+        let response = match request.send().await {
+            Ok(response) => response,
+            Err(e) => {
+                return match cached {
+                    Some(entry) => Ok((serde_json::from_str(&entry.body)?, true)),
+                    None => Err(e.into()),
+                };
+            }
+        };
+
+        if let Some(entry) = cached {
+            if response.status() == StatusCode::NOT_MODIFIED {
+                if let Err(e) = self.response_cache.touch(&key) {
+                    eprintln!("Error updating response cache: {}", e);
+                }
+                return Ok((serde_json::from_str(&entry.body)?, false));
+            }
+
+            if response.status().is_server_error() {
+                return Ok((serde_json::from_str(&entry.body)?, true));
+            }
+        }
+
+        if !response.status().is_success() {
+            let status = response.status();
+
+            let error_text = response
+                .text()
+                .await
+                .unwrap_or_else(|_| "No error message".to_string());
+
+            return Err(JellyfinError::ApiError {
+                status,
+                message: error_text,
+            });
+        }
+
+        let etag = response
+            .headers()
+            .get(ETAG)
+            .and_then(|value| value.to_str().ok())
+            .map(String::from);
+
+        let body = response.text().await?;
+        let parsed = serde_json::from_str::<T>(&body)?;
+
+        if let Err(e) = self.response_cache.put(&key, &body, etag.as_deref()) {
+            eprintln!("Error writing response cache: {}", e);
+        }
+
+        Ok((parsed, false))
+    }
+
     pub async fn authenticate_user_by_name(
         &self,
         username: &str,
@@ -75,6 +196,89 @@ impl JellyfinClient {
         }
     }
 
+    // api keys are sent the same way as user tokens, but aren't tied to a user,
+    // so the user to act as is looked up by id or name
+    pub async fn authenticate_with_api_key(
+        &self,
+        api_key: &str,
+        user: &str,
+    ) -> Result<AuthResponse, JellyfinError> {
+        let users = self.get_users(api_key).await?;
+
+        let user_id = normalize_id(user);
+
+        let user = users
+            .into_iter()
+            .find(|u| normalize_id(&u.id) == user_id || u.name.eq_ignore_ascii_case(user))
+            .ok_or_else(|| JellyfinError::ApiError {
+                status: StatusCode::NOT_FOUND,
+                message: format!("No user matching \"{}\"", user),
+            })?;
+
+        let system_info = self.get_public_system_info().await?;
+
+        Ok(AuthResponse {
+            access_token: api_key.to_string(),
+            server_id: system_info.id,
+            user,
+        })
+    }
+
+    async fn get_users(&self, access_token: &str) -> Result<Vec<UserDetails>, JellyfinError> {
+        let url = format!("{}/Users", self.base_url);
+
+        let response = self
+            .http_client
+            .get(&url)
+            .header(
+                "Authorization",
+                format!(
+                    "MediaBrowser Token=\"{}\", Client=\"{}\", Device=\"{}\", DeviceId=\"{}\", Version=\"{}\"",
+                    access_token, self.app_name, self.device_name, self.device_id, self.app_version
+                ),
+            )
+            .send()
+            .await?;
+
+        if response.status().is_success() {
+            Ok(response.json::<Vec<UserDetails>>().await?)
+        } else {
+            let status = response.status();
+
+            let error_text = response
+                .text()
+                .await
+                .unwrap_or_else(|_| "No error message".to_string());
+
+            Err(JellyfinError::ApiError {
+                status,
+                message: error_text,
+            })
+        }
+    }
+
+    async fn get_public_system_info(&self) -> Result<PublicSystemInfo, JellyfinError> {
+        let url = format!("{}/System/Info/Public", self.base_url);
+
+        let response = self.http_client.get(&url).send().await?;
+
+        if response.status().is_success() {
+            Ok(response.json::<PublicSystemInfo>().await?)
+        } else {
+            let status = response.status();
+
+            let error_text = response
+                .text()
+                .await
+                .unwrap_or_else(|_| "No error message".to_string());
+
+            Err(JellyfinError::ApiError {
+                status,
+                message: error_text,
+            })
+        }
+    }
+
     pub async fn search_albums(
         &self,
         search: &str,
@@ -119,39 +323,19 @@ impl JellyfinClient {
             }
         }
 
-        let response = self
-            .http_client
-            .get(url.to_string())
-            .header(
-                "Authorization",
-                format!(
-                    "MediaBrowser Token=\"{}\", Client=\"{}\", Device=\"{}\", DeviceId=\"{}\", Version=\"{}\"",
-                    access_token, self.app_name, self.device_name, self.device_id, self.app_version
-                ),
-            )
-            .send()
+        let (mut items, stale) = self
+            .get_cached::<JellyfinItemsResponse>(url, access_token, false)
             .await?;
+        items.stale = stale;
 
-        if response.status().is_success() {
-            Ok(response.json::<JellyfinItemsResponse>().await?)
-        } else {
-            let status = response.status();
-
-            let error_text = response
-                .text()
-                .await
-                .unwrap_or_else(|_| "No error message".to_string());
-
-            Err(JellyfinError::ApiError {
-                status,
-                message: error_text,
-            })
-        }
+        Ok(items)
     }
 
     pub async fn search_albums_by_album_artist(
         &self,
         album_artist_ids: Vec<String>,
+        sort_by: Option<&str>,
+        offset: Option<u32>,
         access_token: &str,
         user_id: Option<&str>,
     ) -> Result<JellyfinItemsResponse, JellyfinError> {
@@ -159,9 +343,9 @@ impl JellyfinClient {
             None,
             "MusicAlbum",
             access_token,
+            sort_by,
             None,
-            None,
-            None,
+            offset,
             Some(album_artist_ids),
             user_id,
         )
@@ -196,7 +380,8 @@ impl JellyfinClient {
             .append_pair("recursive", "true")
             .append_pair("limit", &limit.to_string())
             .append_pair("startIndex", &offset.to_string())
-            .append_pair("sortBy", "Album,AlbumArtist");
+            .append_pair("sortBy", sort_by.unwrap_or("Album,AlbumArtist"))
+            .append_pair("fields", ITEM_FIELDS);
 
         if let Some(search_term) = search {
             url.query_pairs_mut().append_pair("searchTerm", search_term);
@@ -208,39 +393,62 @@ impl JellyfinClient {
                 .append_pair("albumArtistIds", &album_artist_ids_str);
         }
 
-        if let Some(sort_by) = sort_by {
-            url.query_pairs_mut().append_pair("sortBy", sort_by);
-        }
+        let (mut items, stale) = self
+            .get_cached::<JellyfinItemsResponse>(url, access_token, false)
+            .await?;
+        items.stale = stale;
 
-        let response = self
-            .http_client
-            .get(&url.to_string())
-            .header(
-                "Authorization",
-                format!(
-                    "MediaBrowser Token=\"{}\", Client=\"{}\", Device=\"{}\", DeviceId=\"{}\", Version=\"{}\"",
-                    access_token, self.app_name, self.device_name, self.device_id, self.app_version
-                ),
-            )
-            .send()
+        Ok(items)
+    }
+
+    pub async fn get_favorite_albums(
+        &self,
+        access_token: &str,
+        user_id: &str,
+    ) -> Result<JellyfinItemsResponse, JellyfinError> {
+        let mut url = Url::parse(&self.base_url)
+            .map_err(|e| JellyfinError::GenericError(format!("Invalid base URL: {}", e)))?;
+
+        // favorites are per user, so there's no version of this without one
+        url.set_path(&format!("/Users/{}/Items", user_id));
+
+        url.query_pairs_mut()
+            .append_pair("includeItemTypes", "MusicAlbum")
+            .append_pair("recursive", "true")
+            .append_pair("filters", "IsFavorite")
+            .append_pair("sortBy", "SortName")
+            .append_pair("fields", ITEM_FIELDS);
+
+        let (mut items, stale) = self
+            .get_cached::<JellyfinItemsResponse>(url, access_token, false)
             .await?;
+        items.stale = stale;
 
-        if response.status().is_success() {
-            let items = response.json::<JellyfinItemsResponse>().await?;
-            Ok(items)
-        } else {
-            let status = response.status();
+        Ok(items)
+    }
 
-            let error_text = response
-                .text()
-                .await
-                .unwrap_or_else(|_| "No error message".to_string());
+    // the tracks in a playlist, in playlist order
+    pub async fn get_playlist_items(
+        &self,
+        playlist_id: &str,
+        access_token: &str,
+        user_id: &str,
+    ) -> Result<JellyfinItemsResponse, JellyfinError> {
+        let mut url = Url::parse(&self.base_url)
+            .map_err(|e| JellyfinError::GenericError(format!("Invalid base URL: {}", e)))?;
 
-            Err(JellyfinError::ApiError {
-                status,
-                message: error_text,
-            })
-        }
+        url.set_path(&format!("/Playlists/{}/Items", playlist_id));
+
+        url.query_pairs_mut()
+            .append_pair("userId", user_id)
+            .append_pair("fields", ITEM_FIELDS);
+
+        let (mut items, stale) = self
+            .get_cached::<JellyfinItemsResponse>(url, access_token, false)
+            .await?;
+        items.stale = stale;
+
+        Ok(items)
     }
 
     pub async fn get_jellyfin_item(
@@ -248,59 +456,136 @@ impl JellyfinClient {
         item_id: &str,
         access_token: &str,
         user_id: Option<&str>,
+        revalidate: bool,
     ) -> Result<JellyfinItem, JellyfinError> {
-        let mut url = Url::parse(&self.base_url)
-            .map_err(|e| JellyfinError::GenericError(format!("Invalid base URL: {}", e)))?;
+        let mut items = self
+            .get_jellyfin_items(&[item_id.to_string()], access_token, user_id, revalidate)
+            .await?;
 
-        if let Some(user_id) = user_id {
-            url.set_path(&format!("/Users/{}/Items", user_id));
+        let first = items.items.drain(..).next();
+
+        if let Some(item) = first {
+            Ok(item)
         } else {
-            url.set_path("/Items");
+            Err(JellyfinError::ApiError {
+                status: StatusCode::NOT_FOUND,
+                message: "Item not found".to_string(),
+            })
         }
+    }
 
-        // TODO consider only requesting the fields we need?
-
-        url.query_pairs_mut()
-            .append_pair("ids", item_id)
-            .append_pair("recursive", "true");
-
-        let response = self
-            .http_client
-            .get(url.to_string())
-            .header(
-                "Authorization",
-                format!(
-                    "MediaBrowser Token=\"{}\", Client=\"{}\", Device=\"{}\", DeviceId=\"{}\", Version=\"{}\"",
-                    access_token, self.app_name, self.device_name, self.device_id, self.app_version
-                ),
-            )
-            .send()
-            .await?;
+    // items that no longer exist on the server are left out of the response.
+    // the ids go in the query string, so long lists are asked for in batches
+    pub async fn get_jellyfin_items(
+        &self,
+        item_ids: &[String],
+        access_token: &str,
+        user_id: Option<&str>,
+        revalidate: bool,
+    ) -> Result<JellyfinItemsResponse, JellyfinError> {
+        let mut items = JellyfinItemsResponse {
+            total_record_count: 0,
+            start_index: 0,
+            items: Vec::new(),
+            stale: false,
+        };
 
-        if response.status().is_success() {
-            let mut items = response.json::<JellyfinItemsResponse>().await?;
-            let first = items.items.drain(..).next();
+        for batch in item_ids.chunks(ITEM_IDS_PER_REQUEST) {
+            let mut url = Url::parse(&self.base_url)
+                .map_err(|e| JellyfinError::GenericError(format!("Invalid base URL: {}", e)))?;
 
-            if let Some(item) = first {
-                Ok(item)
+            if let Some(user_id) = user_id {
+                url.set_path(&format!("/Users/{}/Items", user_id));
             } else {
-                Err(JellyfinError::ApiError {
-                    status: StatusCode::NOT_FOUND,
-                    message: "Item not found".to_string(),
-                })
+                url.set_path("/Items");
             }
-        } else {
-            let status = response.status();
 
-            let error_text = response
-                .text()
-                .await
-                .unwrap_or_else(|_| "No error message".to_string());
+            // TODO consider only requesting the fields we need?
 
-            Err(JellyfinError::ApiError {
-                status,
-                message: error_text,
-            })
+            url.query_pairs_mut()
+                .append_pair("ids", &batch.join(","))
+                .append_pair("recursive", "true")
+                .append_pair("fields", ITEM_FIELDS);
+
+            let (page, stale) = self
+                .get_cached::<JellyfinItemsResponse>(url, access_token, revalidate)
+                .await?;
+
+            items.total_record_count += page.total_record_count;
+            items.items.extend(page.items);
+            items.stale |= stale;
+        }
+
+        Ok(items)
+    }
+
+    // albums and tracks saved (added or modified) since the given time, with
+    // when each was last saved. a page at a time, since it can be a lot
+    pub async fn get_items_changed_since(
+        &self,
+        since: &NaiveDateTime,
+        access_token: &str,
+    ) -> Result<JellyfinItemsResponse, JellyfinError> {
+        let mut items = Vec::new();
+
+        loop {
+            let mut url = Url::parse(&self.base_url)
+                .map_err(|e| JellyfinError::GenericError(format!("Invalid base URL: {}", e)))?;
+
+            url.set_path("/Items");
+
+            url.query_pairs_mut()
+                .append_pair("includeItemTypes", "MusicAlbum,Audio")
+                .append_pair("recursive", "true")
+                .append_pair(
+                    "minDateLastSaved",
+                    &since.format("%Y-%m-%dT%H:%M:%SZ").to_string(),
+                )
+                .append_pair("fields", "DateLastSaved")
+                .append_pair("sortBy", "DateLastSaved,SortName")
+                .append_pair("startIndex", &items.len().to_string())
+                .append_pair("limit", &CHANGED_ITEMS_PAGE_SIZE.to_string());
+
+            let response = self
+                .http_client
+                .get(url.to_string())
+                .header(
+                    "Authorization",
+                    format!(
+                        "MediaBrowser Token=\"{}\", Client=\"{}\", Device=\"{}\", DeviceId=\"{}\", Version=\"{}\"",
+                        access_token, self.app_name, self.device_name, self.device_id, self.app_version
+                    ),
+                )
+                .send()
+                .await?;
+
+            if !response.status().is_success() {
+                let status = response.status();
+
+                let error_text = response
+                    .text()
+                    .await
+                    .unwrap_or_else(|_| "No error message".to_string());
+
+                return Err(JellyfinError::ApiError {
+                    status,
+                    message: error_text,
+                });
+            }
+
+            let page = response.json::<JellyfinItemsResponse>().await?;
+            let page_len = page.items.len();
+            let total = page.total_record_count;
+            items.extend(page.items);
+
+            if page_len == 0 || items.len() >= total as usize {
+                return Ok(JellyfinItemsResponse {
+                    total_record_count: total,
+                    start_index: 0,
+                    items,
+                    stale: false,
+                });
+            }
         }
     }
 
@@ -308,64 +593,70 @@ impl JellyfinClient {
         &self,
         album_id: &str,
         access_token: &str,
+        user_id: Option<&str>,
+        revalidate: bool,
     ) -> Result<JellyfinItemsResponse, JellyfinError> {
-        let url = format!(
-            "{}/Items?parentId={}&recursive=true&sortBy=IndexNumber",
-            self.base_url, album_id
-        );
-
-        let response = self
-            .http_client
-            .get(&url)
-            .header(
-                "Authorization",
-                format!(
-                    "MediaBrowser Token=\"{}\", Client=\"{}\", Device=\"{}\", DeviceId=\"{}\", Version=\"{}\"",
-                    access_token, self.app_name, self.device_name, self.device_id, self.app_version
-                ),
-            )
-            .send()
-            .await?;
+        let mut url = Url::parse(&self.base_url)
+            .map_err(|e| JellyfinError::GenericError(format!("Invalid base URL: {}", e)))?;
 
-        if response.status().is_success() {
-            let items = response.json::<JellyfinItemsResponse>().await?;
-            Ok(items)
+        // user data (favorites, play counts) only comes back for user queries
+        if let Some(user_id) = user_id {
+            url.set_path(&format!("/Users/{}/Items", user_id));
         } else {
-            let status = response.status();
+            url.set_path("/Items");
+        }
 
-            let error_text = response
-                .text()
-                .await
-                .unwrap_or_else(|_| "No error message".to_string());
+        url.query_pairs_mut()
+            .append_pair("parentId", album_id)
+            .append_pair("recursive", "true")
+            .append_pair("sortBy", "ParentIndexNumber,IndexNumber")
+            .append_pair("fields", TRACK_FIELDS);
 
-            Err(JellyfinError::ApiError {
-                status,
-                message: error_text,
-            })
-        }
+        let (mut items, stale) = self
+            .get_cached::<JellyfinItemsResponse>(url, access_token, revalidate)
+            .await?;
+        items.stale = stale;
+
+        Ok(items)
     }
 
+    #[allow(clippy::too_many_arguments)]
     pub async fn download_track(
         &self,
         track_id: &str,
         download_path: &str,
         access_token: &str,
-    ) -> Result<(), JellyfinError> {
-        let url = format!("{}/Items/{}/Download", self.base_url, track_id);
+        user_id: Option<&str>,
+        quality: &DownloadQuality,
+        mut on_progress: impl FnMut(u64, Option<u64>),
+        cancelled: &AtomicBool,
+        limiter: &BandwidthLimiter,
+    ) -> Result<FileDigest, JellyfinError> {
+        let url = match quality.codec() {
+            Some(_) => self
+                .universal_audio_url(track_id, user_id, quality)?
+                .to_string(),
+            None => format!("{}/Items/{}/Download", self.base_url, track_id),
+        };
 
-        let response = self
-            .http_client
-            .get(&url)
-            .header(
-                "Authorization",
-                format!(
-                    "MediaBrowser Token=\"{}\", Client=\"{}\", Device=\"{}\", DeviceId=\"{}\", Version=\"{}\"",
-                    access_token, self.app_name, self.device_name, self.device_id, self.app_version
-                ),
-            )
-            .send()
+        // the file is written under a temporary name and only moved into place
+        // once complete. a part file left by an earlier attempt is resumed
+        let part_path = format!("{}.part", download_path);
+
+        let resume_from = tokio::fs::metadata(&part_path)
+            .await
+            .map(|metadata| metadata.len())
+            .unwrap_or(0);
+
+        let mut response = self
+            .send_track_request(&url, access_token, resume_from)
             .await?;
 
+        // the part file is bigger than the track, it can't be resumed
+        if response.status() == StatusCode::RANGE_NOT_SATISFIABLE {
+            response = self.send_track_request(&url, access_token, 0).await?;
+        }
+
         if !response.status().is_success() {
             let status = response.status();
             let body = response.text().await.unwrap_or_else(|_| "N/A".to_string());
@@ -375,17 +666,57 @@ impl JellyfinClient {
             });
         }
 
-        let mut dest_file = tokio::fs::File::create(&download_path)
-            .await
-            .map_err(|e| JellyfinError::GenericError(format!("Failed to create file: {}", e)))?;
+        // servers that ignore the range (like transcodes) send the whole file
+        let resuming = response.status() == StatusCode::PARTIAL_CONTENT;
+        let mut bytes_received: u64 = if resuming { resume_from } else { 0 };
+
+        // transcodes are streamed, so the size isn't always known up front
+        let bytes_total = response
+            .content_length()
+            .map(|length| length + bytes_received);
+
+        // the file is hashed as it's written, carrying on from what's resumed
+        let mut hasher = if resuming {
+            let part_path = PathBuf::from(&part_path);
+
+            tokio::task::spawn_blocking(move || FileHasher::from_file(&part_path))
+                .await
+                .map_err(|e| JellyfinError::GenericError(e.to_string()))?
+                .map_err(|e| JellyfinError::GenericError(format!("Failed to hash file: {}", e)))?
+        } else {
+            FileHasher::default()
+        };
+
+        let mut dest_file = if resuming {
+            tokio::fs::OpenOptions::new()
+                .append(true)
+                .open(&part_path)
+                .await
+                .map_err(|e| JellyfinError::GenericError(format!("Failed to open file: {}", e)))?
+        } else {
+            tokio::fs::File::create(&part_path)
+                .await
+                .map_err(|e| JellyfinError::GenericError(format!("Failed to create file: {}", e)))?
+        };
 
         let mut stream = response.bytes_stream();
 
         while let Some(chunk_result) = stream.next().await {
+            // dropping the stream closes the connection
+            if cancelled.load(Ordering::Relaxed) {
+                return Err(JellyfinError::Cancelled);
+            }
+
             let chunk = chunk_result?;
             dest_file.write_all(&chunk).await.map_err(|e| {
                 JellyfinError::GenericError(format!("Failed to write chunk: {}", e))
             })?;
+            hasher.update(&chunk);
+
+            bytes_received += chunk.len() as u64;
+            on_progress(bytes_received, bytes_total);
+
+            limiter.acquire(chunk.len() as u64).await;
         }
 
         dest_file
@@ -393,24 +724,188 @@ impl JellyfinClient {
             .await
             .map_err(|e| JellyfinError::GenericError(format!("Failed to flush file: {}", e)))?;
 
-        Ok(())
+        drop(dest_file);
+
+        // the connection closed early. the part file is kept to resume from
+        if let Some(expected) = bytes_total.filter(|&total| total != bytes_received) {
+            return Err(JellyfinError::IncompleteDownload {
+                received: bytes_received,
+                expected,
+            });
+        }
+
+        tokio::fs::rename(&part_path, &download_path)
+            .await
+            .map_err(|e| JellyfinError::GenericError(format!("Failed to rename file: {}", e)))?;
+
+        Ok(hasher.finish())
+    }
+
+    // asks for the rest of the file after resume_from, or all of it if zero
+    async fn send_track_request(
+        &self,
+        url: &str,
+        access_token: &str,
+        resume_from: u64,
+    ) -> Result<reqwest::Response, JellyfinError> {
+        let mut request = self.http_client.get(url).header(
+            "Authorization",
+            format!(
+                "MediaBrowser Token=\"{}\", Client=\"{}\", Device=\"{}\", DeviceId=\"{}\", Version=\"{}\"",
+                access_token, self.app_name, self.device_name, self.device_id, self.app_version
+            ),
+        );
+
+        if resume_from > 0 {
+            request = request.header(RANGE, format!("bytes={}-", resume_from));
+        }
+
+        Ok(request.send().await?)
     }
 
-    pub async fn download_album_art(
+    // builds a url for jellyfin's universal audio endpoint, which transcodes to
+    // the requested codec and bitrate when the source doesn't already match
+    fn universal_audio_url(
+        &self,
+        track_id: &str,
+        user_id: Option<&str>,
+        quality: &DownloadQuality,
+    ) -> Result<Url, JellyfinError> {
+        let mut url = Url::parse(&self.base_url)
+            .map_err(|e| JellyfinError::GenericError(format!("Invalid base URL: {}", e)))?;
+
+        url.set_path(&format!("/Audio/{}/universal", track_id));
+
+        {
+            let mut query = url.query_pairs_mut();
+            query.append_pair("deviceId", &self.device_id);
+            query.append_pair("transcodingProtocol", "http");
+
+            if let Some(user_id) = user_id {
+                query.append_pair("userId", user_id);
+            }
+
+            if let Some(extension) = quality.extension() {
+                query.append_pair("container", extension);
+            }
+
+            if let Some(container) = quality.transcoding_container() {
+                query.append_pair("transcodingContainer", container);
+            }
+
+            if let Some(codec) = quality.codec() {
+                query.append_pair("audioCodec", codec);
+            }
+
+            if let Some(bitrate) = quality.bitrate() {
+                query.append_pair("maxStreamingBitrate", &bitrate.to_string());
+            }
+        }
+
+        Ok(url)
+    }
+
+    // builds an authenticated url the player can stream from directly. formats the
+    // webview can play are sent as-is, anything else is transcoded to mp3
+    pub fn get_stream_url(
+        &self,
+        track_id: &str,
+        access_token: &str,
+        user_id: Option<&str>,
+    ) -> Result<String, JellyfinError> {
+        let mut url = Url::parse(&self.base_url)
+            .map_err(|e| JellyfinError::GenericError(format!("Invalid base URL: {}", e)))?;
+
+        url.set_path(&format!("/Audio/{}/universal", track_id));
+
+        {
+            let mut query = url.query_pairs_mut();
+            query.append_pair("api_key", access_token);
+            query.append_pair("deviceId", &self.device_id);
+            query.append_pair("container", "mp3,flac,ogg,opus,webm,m4a,aac,wav");
+            query.append_pair("transcodingContainer", "mp3");
+            query.append_pair("transcodingProtocol", "http");
+            query.append_pair("audioCodec", "mp3");
+
+            if let Some(user_id) = user_id {
+                query.append_pair("userId", user_id);
+            }
+        }
+
+        Ok(url.to_string())
+    }
+
+    // saves an image at its original resolution to download_dir/file_stem, with
+    // an extension matching what the server sent. returns None if the item has
+    // no image of that type
+    pub async fn download_image(
+        &self,
+        item_id: &str,
+        image_type: &str,
+        image_tag: Option<&str>,
+        download_dir: &Path,
+        file_stem: &str,
+        access_token: &str,
+    ) -> Result<Option<PathBuf>, JellyfinError> {
+        let mut url = Url::parse(&self.base_url)
+            .map_err(|e| JellyfinError::GenericError(format!("Invalid base URL: {}", e)))?;
+
+        url.set_path(&format!("/Items/{}/Images/{}", item_id, image_type));
+
+        if let Some(image_tag) = image_tag {
+            url.query_pairs_mut().append_pair("tag", image_tag);
+        }
+
+        self.save_image(url, download_dir, file_stem, access_token)
+            .await
+    }
+
+    fn thumbnail_url(
         &self,
         item_id: &str,
         image_tag: &str,
-        download_path: &str,
+        size: u32,
+    ) -> Result<Url, JellyfinError> {
+        let mut url = Url::parse(&self.base_url)
+            .map_err(|e| JellyfinError::GenericError(format!("Invalid base URL: {}", e)))?;
+
+        url.set_path(&format!("/Items/{}/Images/Primary", item_id));
+
+        url.query_pairs_mut()
+            .append_pair("fillHeight", &size.to_string())
+            .append_pair("fillWidth", &size.to_string())
+            .append_pair("quality", "90")
+            .append_pair("tag", image_tag);
+
+        Ok(url)
+    }
+
+    // saves a scaled down primary image, for browsing
+    pub async fn download_thumbnail(
+        &self,
+        item_id: &str,
+        image_tag: &str,
+        size: u32,
+        download_dir: &Path,
+        file_stem: &str,
         access_token: &str,
-    ) -> Result<(), JellyfinError> {
-        let url = format!(
-            "{}/Items/{}/Images/Primary?fillHeight=300&fillWidth=300&quality=96&tag={}",
-            self.base_url, item_id, image_tag
-        );
+    ) -> Result<Option<PathBuf>, JellyfinError> {
+        let url = self.thumbnail_url(item_id, image_tag, size)?;
 
+        self.save_image(url, download_dir, file_stem, access_token)
+            .await
+    }
+
+    async fn save_image(
+        &self,
+        url: Url,
+        download_dir: &Path,
+        file_stem: &str,
+        access_token: &str,
+    ) -> Result<Option<PathBuf>, JellyfinError> {
         let response = self
             .http_client
-            .get(&url)
+            .get(url.to_string())
             .header(
                 "Authorization",
                 format!(
@@ -421,6 +916,10 @@ impl JellyfinClient {
             .send()
             .await?;
 
+        if response.status() == StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+
         if !response.status().is_success() {
             let status = response.status();
             let body = response.text().await.unwrap_or_else(|_| "N/A".to_string());
@@ -430,6 +929,19 @@ impl JellyfinClient {
             });
         }
 
+        let extension = match response
+            .headers()
+            .get(reqwest::header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+        {
+            Some("image/png") => "png",
+            Some("image/webp") => "webp",
+            Some("image/gif") => "gif",
+            _ => "jpg",
+        };
+
+        let download_path = download_dir.join(format!("{}.{}", file_stem, extension));
+
         let mut dest_file = tokio::fs::File::create(&download_path)
             .await
             .map_err(|e| JellyfinError::GenericError(format!("Failed to create file: {}", e)))?;
@@ -448,36 +960,19 @@ impl JellyfinClient {
             .await
             .map_err(|e| JellyfinError::GenericError(format!("Failed to flush file: {}", e)))?;
 
-        Ok(())
+        Ok(Some(download_path))
     }
 
-    pub async fn get_recents(
+    pub async fn report_capabilities(
         &self,
+        capabilities: &ClientCapabilities,
         access_token: &str,
-        limit: Option<u32>,
-        offset: Option<u32>,
-        user_id: Option<&str>,
-    ) -> Result<JellyfinItemsResponse, JellyfinError> {
-        let limit = limit.unwrap_or(100);
-        let offset = offset.unwrap_or(0);
-
-        let mut url = Url::parse(&self.base_url)
-            .map_err(|e| JellyfinError::GenericError(format!("Invalid base URL: {}", e)))?;
-
-        if let Some(user_id) = user_id {
-            url.set_path(&format!("/Users/{}/Items/Latest", user_id));
-        } else {
-            url.set_path("/Items/Latest");
-        }
-
-        url.query_pairs_mut()
-            .append_pair("includeItemTypes", "MusicAlbum")
-            .append_pair("limit", &limit.to_string())
-            .append_pair("startIndex", &offset.to_string());
+    ) -> Result<(), JellyfinError> {
+        let url = format!("{}/Sessions/Capabilities/Full", self.base_url);
 
         let response = self
             .http_client
-            .get(&url.to_string())
+            .post(&url)
             .header(
                 "Authorization",
                 format!(
@@ -485,17 +980,12 @@ impl JellyfinClient {
                     access_token, self.app_name, self.device_name, self.device_id, self.app_version
                 ),
             )
+            .json(capabilities)
             .send()
             .await?;
 
         if response.status().is_success() {
-            let items = response.json::<Vec<JellyfinItem>>().await?;
-
-            Ok(JellyfinItemsResponse {
-                total_record_count: items.len() as u32,
-                start_index: offset,
-                items,
-            })
+            Ok(())
         } else {
             let status = response.status();
 
@@ -510,4 +1000,66 @@ impl JellyfinClient {
             })
         }
     }
+
+    // the session websocket lives at /socket on the same host, over ws or wss
+    pub fn get_websocket_url(&self, access_token: &str) -> Result<Url, JellyfinError> {
+        let mut url = Url::parse(&self.base_url)
+            .map_err(|e| JellyfinError::GenericError(format!("Invalid base URL: {}", e)))?;
+
+        let scheme = if url.scheme() == "https" { "wss" } else { "ws" };
+
+        url.set_scheme(scheme)
+            .map_err(|_| JellyfinError::GenericError("Invalid websocket scheme".to_string()))?;
+
+        url.set_path("/socket");
+
+        url.query_pairs_mut()
+            .append_pair("api_key", access_token)
+            .append_pair("deviceId", &self.device_id);
+
+        Ok(url)
+    }
+
+    pub async fn get_recents(
+        &self,
+        access_token: &str,
+        limit: Option<u32>,
+        offset: Option<u32>,
+        user_id: Option<&str>,
+    ) -> Result<JellyfinItemsResponse, JellyfinError> {
+        let limit = limit.unwrap_or(100);
+        let offset = offset.unwrap_or(0);
+
+        let mut url = Url::parse(&self.base_url)
+            .map_err(|e| JellyfinError::GenericError(format!("Invalid base URL: {}", e)))?;
+
+        if let Some(user_id) = user_id {
+            url.set_path(&format!("/Users/{}/Items/Latest", user_id));
+        } else {
+            url.set_path("/Items/Latest");
+        }
+
+        url.query_pairs_mut()
+            .append_pair("includeItemTypes", "MusicAlbum")
+            .append_pair("limit", &limit.to_string())
+            .append_pair("startIndex", &offset.to_string())
+            .append_pair("fields", ITEM_FIELDS);
+
+        let (items, stale) = self
+            .get_cached::<Vec<JellyfinItem>>(url, access_token, false)
+            .await?;
+
+        Ok(JellyfinItemsResponse {
+            total_record_count: items.len() as u32,
+            start_index: offset,
+            items,
+            stale,
+        })
+    }
+}
+
+// jellyfin ids are guids, written with or without dashes depending on where
+// they come from
+fn normalize_id(id: &str) -> String {
+    id.replace('-', "").to_lowercase()
 }
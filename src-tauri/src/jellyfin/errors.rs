@@ -20,6 +20,15 @@ pub enum JellyfinError {
     #[error("Database error: {0}")]
     DbError(#[from] diesel::result::Error),
 
+    #[error("Download cancelled")]
+    Cancelled,
+
+    #[error("Download incomplete: received {received} of {expected} bytes")]
+    IncompleteDownload { received: u64, expected: u64 },
+
+    #[error("Not enough space: {required} bytes needed, {available} available")]
+    InsufficientSpace { required: u64, available: u64 },
+
     #[error("Jellyfin Error: {0}")]
     GenericError(String),
 }
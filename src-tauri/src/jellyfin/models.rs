@@ -1,5 +1,9 @@
+use chrono::{DateTime, NaiveDateTime, Utc};
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
 use std::hash::{Hash, Hasher};
+use std::str::FromStr;
 
 #[derive(Serialize)]
 #[serde(rename_all = "PascalCase")]
@@ -24,6 +28,12 @@ pub struct UserDetails {
     pub primary_image_tag: Option<String>,
 }
 
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "PascalCase")]
+pub struct PublicSystemInfo {
+    pub id: String,
+}
+
 #[derive(Deserialize, Debug, Clone, Serialize)]
 pub struct SessionResponse {
     pub authenticated: bool,
@@ -35,6 +45,9 @@ pub struct JellyfinItemsResponse {
     pub total_record_count: u32,
     pub start_index: u32,
     pub items: Vec<JellyfinItem>,
+    // served from the response cache because the server couldn't be reached
+    #[serde(skip)]
+    pub stale: bool,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -46,12 +59,82 @@ pub struct JellyfinItem {
     pub container: Option<String>,
     pub index_number: Option<u32>,
     pub image_tags: Option<JellyfinImageTags>,
+    #[serde(rename(deserialize = "Type"))]
+    pub item_type: Option<String>,
+    pub album_id: Option<String>,
+    pub production_year: Option<i32>,
+    pub genres: Option<Vec<String>>,
+    pub run_time_ticks: Option<i64>,
+    pub artist_items: Option<Vec<JellyfinNameIdPair>>,
+    pub album_artists: Option<Vec<JellyfinNameIdPair>>,
+    pub parent_index_number: Option<u32>,
+    pub overview: Option<String>,
+    pub date_created: Option<DateTime<Utc>>,
+    pub user_data: Option<JellyfinUserData>,
+    pub media_sources: Option<Vec<JellyfinMediaSource>>,
+    pub date_last_saved: Option<DateTime<Utc>>,
+}
+
+impl JellyfinItem {
+    pub fn artist_names(&self) -> Vec<String> {
+        self.artist_items
+            .iter()
+            .flatten()
+            .map(|artist| artist.name.clone())
+            .collect()
+    }
+
+    pub fn is_favorite(&self) -> bool {
+        self.user_data
+            .as_ref()
+            .and_then(|data| data.is_favorite)
+            .unwrap_or(false)
+    }
+
+    pub fn play_count(&self) -> u32 {
+        self.user_data
+            .as_ref()
+            .and_then(|data| data.play_count)
+            .unwrap_or(0)
+    }
+
+    // the size of the original file, only asked for when listing tracks
+    pub fn file_size(&self) -> Option<u64> {
+        self.media_sources
+            .iter()
+            .flatten()
+            .find_map(|source| source.size)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all(serialize = "camelCase", deserialize = "PascalCase"))]
+pub struct JellyfinNameIdPair {
+    pub name: String,
+    pub id: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all(serialize = "camelCase", deserialize = "PascalCase"))]
+pub struct JellyfinUserData {
+    pub played: Option<bool>,
+    pub play_count: Option<u32>,
+    pub is_favorite: Option<bool>,
+    pub last_played_date: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all(serialize = "camelCase", deserialize = "PascalCase"))]
+pub struct JellyfinMediaSource {
+    pub size: Option<u64>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all(serialize = "camelCase", deserialize = "PascalCase"))]
 pub struct JellyfinImageTags {
     pub primary: Option<String>,
+    pub box_rear: Option<String>,
+    pub disc: Option<String>,
 }
 
 impl PartialEq for JellyfinItem {
@@ -68,12 +151,60 @@ impl Hash for JellyfinItem {
     }
 }
 
+#[derive(Serialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct ClientCapabilities {
+    pub playable_media_types: Vec<String>,
+    pub supported_commands: Vec<String>,
+    pub supports_media_control: bool,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct WebSocketMessage {
+    pub message_type: String,
+    #[serde(default)]
+    pub data: serde_json::Value,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct PlayRequest {
+    pub item_ids: Vec<String>,
+    pub start_index: Option<u32>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct PlaystateRequest {
+    pub command: String,
+    pub seek_position_ticks: Option<i64>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct GeneralCommand {
+    pub name: String,
+    #[serde(default)]
+    pub arguments: HashMap<String, String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "PascalCase", default)]
+pub struct LibraryUpdateInfo {
+    pub items_added: Vec<String>,
+    pub items_updated: Vec<String>,
+    pub items_removed: Vec<String>,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all(serialize = "camelCase", deserialize = "PascalCase"))]
 pub struct AlbumSearchResponse {
     pub total_record_count: u32,
     pub start_index: u32,
     pub items: Vec<AlbumSearchResponseItem>,
+    #[serde(default)]
+    pub stale: bool,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -84,20 +215,238 @@ pub struct AlbumSearchResponseItem {
     pub album_artist: String,
     pub downloaded: bool,
     pub image_url: Option<String>,
+    pub outdated: Option<String>,
 }
 
 #[derive(Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct AlbumInfoResponse {
+    pub id: String,
     pub name: String,
     pub artist: String,
     pub tracks: Vec<AlbumTrackResponse>,
     pub image_url: Option<String>,
+    pub back_image_url: Option<String>,
+    pub disc_image_url: Option<String>,
+    pub artist_image_url: Option<String>,
+    pub quality: String,
+    pub production_year: Option<i32>,
+    pub genres: Vec<String>,
+    pub artists: Vec<String>,
+    pub duration_seconds: Option<f64>,
+    pub overview: Option<String>,
+    pub date_created: Option<NaiveDateTime>,
+    pub is_favorite: bool,
+    pub play_count: u32,
+    pub discs: Vec<AlbumDiscResponse>,
+}
+
+// a run of tracks in AlbumInfoResponse.tracks that belong to the same disc
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AlbumDiscResponse {
+    pub disc_number: u32,
+    pub start_index: usize,
+    pub track_count: usize,
 }
 
 #[derive(Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct AlbumTrackResponse {
+    pub id: String,
     pub name: String,
     pub playback_url: String,
+    // false for tracks a partial download left out, which can't be played
+    pub available: bool,
+    pub disc_number: Option<u32>,
+    pub artists: Vec<String>,
+    pub duration_seconds: Option<f64>,
+    pub is_favorite: bool,
+    pub play_count: u32,
+}
+
+// groups the (already disc-ordered) tracks by disc. tracks without a disc
+// number are treated as disc 1
+pub fn group_discs(tracks: &[AlbumTrackResponse]) -> Vec<AlbumDiscResponse> {
+    let mut discs: Vec<AlbumDiscResponse> = Vec::new();
+
+    for (index, track) in tracks.iter().enumerate() {
+        let disc_number = track.disc_number.unwrap_or(1);
+
+        match discs.last_mut() {
+            Some(disc) if disc.disc_number == disc_number => disc.track_count += 1,
+            _ => discs.push(AlbumDiscResponse {
+                disc_number,
+                start_index: index,
+                track_count: 1,
+            }),
+        }
+    }
+
+    discs
+}
+
+// jellyfin measures durations in 100ns ticks
+pub fn ticks_to_seconds(ticks: i64) -> f64 {
+    ticks as f64 / 10_000_000.0
+}
+
+// jellyfin reads maxStreamingBitrate (bits per second) as an i32
+const MAX_BITRATE_KBPS: u32 = i32::MAX as u32 / 1000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(tag = "codec", rename_all = "camelCase")]
+pub enum DownloadQuality {
+    #[default]
+    Original,
+    Opus {
+        bitrate: u32,
+    },
+    Mp3 {
+        bitrate: u32,
+    },
+}
+
+impl DownloadQuality {
+    // the audio codec to transcode to, or None to fetch the original file
+    pub fn codec(&self) -> Option<&'static str> {
+        match self {
+            DownloadQuality::Original => None,
+            DownloadQuality::Opus { .. } => Some("opus"),
+            DownloadQuality::Mp3 { .. } => Some("mp3"),
+        }
+    }
+
+    // the container jellyfin should wrap the transcoded stream in
+    pub fn transcoding_container(&self) -> Option<&'static str> {
+        match self {
+            DownloadQuality::Original => None,
+            DownloadQuality::Opus { .. } => Some("ogg"),
+            DownloadQuality::Mp3 { .. } => Some("mp3"),
+        }
+    }
+
+    // the file extension for transcoded files
+    pub fn extension(&self) -> Option<&'static str> {
+        match self {
+            DownloadQuality::Original => None,
+            DownloadQuality::Opus { .. } => Some("opus"),
+            DownloadQuality::Mp3 { .. } => Some("mp3"),
+        }
+    }
+
+    // bitrate in bits per second
+    pub fn bitrate(&self) -> Option<u64> {
+        match self {
+            DownloadQuality::Original => None,
+            DownloadQuality::Opus { bitrate } | DownloadQuality::Mp3 { bitrate } => {
+                Some(u64::from(*bitrate) * 1000)
+            }
+        }
+    }
+
+    // transcodes need a bitrate jellyfin can take
+    pub fn validate(&self) -> Result<(), String> {
+        match self {
+            DownloadQuality::Original => Ok(()),
+            DownloadQuality::Opus { bitrate } | DownloadQuality::Mp3 { bitrate } => {
+                if (1..=MAX_BITRATE_KBPS).contains(bitrate) {
+                    Ok(())
+                } else {
+                    Err(format!("Invalid bitrate: {}", bitrate))
+                }
+            }
+        }
+    }
+}
+
+// stored in the albums table as "original", "opus-128", "mp3-320", etc.
+impl fmt::Display for DownloadQuality {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DownloadQuality::Original => write!(f, "original"),
+            DownloadQuality::Opus { bitrate } => write!(f, "opus-{}", bitrate),
+            DownloadQuality::Mp3 { bitrate } => write!(f, "mp3-{}", bitrate),
+        }
+    }
+}
+
+impl FromStr for DownloadQuality {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s == "original" {
+            return Ok(DownloadQuality::Original);
+        }
+
+        let (codec, bitrate) = s
+            .split_once('-')
+            .ok_or_else(|| format!("Invalid download quality: {}", s))?;
+
+        let bitrate = bitrate
+            .parse::<u32>()
+            .map_err(|_| format!("Invalid bitrate: {}", bitrate))?;
+
+        let quality = match codec {
+            "opus" => DownloadQuality::Opus { bitrate },
+            "mp3" => DownloadQuality::Mp3 { bitrate },
+            _ => return Err(format!("Unsupported codec: {}", codec)),
+        };
+
+        quality.validate()?;
+        Ok(quality)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn quality_round_trips_through_its_string_form() {
+        for quality in [
+            DownloadQuality::Original,
+            DownloadQuality::Opus { bitrate: 128 },
+            DownloadQuality::Mp3 { bitrate: 320 },
+        ] {
+            assert_eq!(quality.to_string().parse::<DownloadQuality>(), Ok(quality));
+        }
+    }
+
+    #[test]
+    fn quality_rejects_a_zero_or_missing_bitrate() {
+        assert!("opus-0".parse::<DownloadQuality>().is_err());
+        assert!("opus-".parse::<DownloadQuality>().is_err());
+        assert!("mp3-fast".parse::<DownloadQuality>().is_err());
+    }
+
+    #[test]
+    fn quality_rejects_a_bitrate_jellyfin_cant_take() {
+        assert!("mp3-4294968".parse::<DownloadQuality>().is_err());
+        assert!(DownloadQuality::Opus { bitrate: u32::MAX }
+            .validate()
+            .is_err());
+        assert!(DownloadQuality::Mp3 { bitrate: 0 }.validate().is_err());
+        assert_eq!(DownloadQuality::Original.validate(), Ok(()));
+    }
+
+    #[test]
+    fn bitrate_is_in_bits_per_second_without_overflowing() {
+        assert_eq!(
+            DownloadQuality::Opus { bitrate: 128 }.bitrate(),
+            Some(128_000)
+        );
+        assert_eq!(
+            DownloadQuality::Mp3 { bitrate: u32::MAX }.bitrate(),
+            Some(u32::MAX as u64 * 1000)
+        );
+        assert_eq!(DownloadQuality::Original.bitrate(), None);
+    }
+
+    #[test]
+    fn quality_rejects_unknown_codecs() {
+        assert!("flac-900".parse::<DownloadQuality>().is_err());
+        assert!("opus".parse::<DownloadQuality>().is_err());
+        assert!("".parse::<DownloadQuality>().is_err());
+    }
 }
@@ -1,36 +1,58 @@
+use chrono::Duration;
 use serde_json::json;
 use std::env;
 use std::sync::{Arc, Mutex};
 use std::thread;
+use tauri::http;
 use tauri::Manager;
 use tauri::State;
 use tauri_plugin_store::StoreExt;
 use uuid::Uuid;
 
-use crate::download_queue::{process_downloads, DownloadQueue};
+use crate::album_refresh::{run_album_refresh, AlbumRefresh};
+use crate::download_queue::{process_downloads, DownloadQueue, DownloadQueueResponse};
+use crate::download_rules::{run_rule_sync, DownloadRuleResponse, RuleCriteria, RuleSyncSummary};
+use crate::image_cache::ImageCache;
+use crate::integrity::LibraryVerification;
 use crate::jellyfin::client::JellyfinClient;
 use crate::jellyfin::models::{
     AlbumInfoResponse, AlbumSearchResponse, AuthResponse, SessionResponse,
 };
 use crate::music_manager::MusicManager;
+use crate::remote_control::run_remote_control;
 use crate::repository::Repository;
+use crate::response_cache::ResponseCache;
+use crate::settings::{DownloadSettings, DOWNLOAD_SETTINGS_KEY};
 
 use diesel_migrations::{embed_migrations, EmbeddedMigrations, MigrationHarness};
 
 pub const MIGRATIONS: EmbeddedMigrations = embed_migrations!();
 
+const IMAGE_CACHE_MAX_BYTES: u64 = 200 * 1024 * 1024;
+const RESPONSE_CACHE_TTL_MINUTES: i64 = 5;
+const RESPONSE_CACHE_MAX_AGE_DAYS: i64 = 30;
+
+mod album_refresh;
 mod db;
 mod download_queue;
+mod download_rules;
+mod image_cache;
+mod integrity;
 mod jellyfin;
 mod models;
 mod music_manager;
+mod rate_limit;
+mod remote_control;
 mod repository;
+mod response_cache;
 mod schema;
+mod settings;
 
 pub struct AppState {
     music_manager: Arc<MusicManager>,
     auth_token: Arc<Mutex<Option<String>>>,
     user_id: Arc<Mutex<Option<String>>>,
+    download_settings: Arc<Mutex<DownloadSettings>>,
     download_queue: DownloadQueue,
 }
 
@@ -104,6 +126,27 @@ async fn authenticate_user_by_name_cmd(
     Ok(response)
 }
 
+// for kiosk and automation setups, an api key acts on behalf of the given user
+#[tauri::command]
+async fn authenticate_with_api_key_cmd(
+    app_handle: tauri::AppHandle,
+    api_key: String,
+    user: String,
+    state: State<'_, AppState>,
+) -> Result<AuthResponse, String> {
+    let music_manager = &state.music_manager;
+
+    let response = music_manager
+        .authenticate_with_api_key(&api_key, &user)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    set_access_token(&app_handle, &state, &response.access_token).await;
+    set_user_id(&app_handle, &state, &response.user.id).await;
+
+    Ok(response)
+}
+
 #[tauri::command]
 async fn search_albums(
     search: String,
@@ -145,14 +188,219 @@ async fn download_album(
 ) -> Result<(), String> {
     let music_manager = &state.music_manager;
 
+    let access_token = get_access_token(&state).await?;
+    let user_id = get_user_id(&state).await?;
+    let quality = state.download_settings.lock().unwrap().quality;
+
+    music_manager
+        .download_album(
+            &app_handle,
+            &album_id,
+            None,
+            &access_token,
+            &user_id,
+            quality,
+        )
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn download_tracks(
+    app_handle: tauri::AppHandle,
+    album_id: String,
+    track_ids: Vec<String>,
+    state: State<'_, AppState>,
+) -> Result<(), String> {
+    if track_ids.is_empty() {
+        return Err("No tracks selected".to_string());
+    }
+
+    let music_manager = &state.music_manager;
+
+    let access_token = get_access_token(&state).await?;
     let user_id = get_user_id(&state).await?;
+    let quality = state.download_settings.lock().unwrap().quality;
 
     music_manager
-        .download_album(&app_handle, &album_id, &user_id)
+        .download_album(
+            &app_handle,
+            &album_id,
+            Some(track_ids),
+            &access_token,
+            &user_id,
+            quality,
+        )
+        .await
+        .map_err(|e| e.to_string())
+}
+
+// hashing every downloaded track takes a while, so it's kept off the main thread
+#[tauri::command]
+async fn verify_library(state: State<'_, AppState>) -> Result<LibraryVerification, String> {
+    let music_manager = state.music_manager.clone();
+
+    tauri::async_runtime::spawn_blocking(move || music_manager.verify_library())
+        .await
+        .map_err(|e| e.to_string())?
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn repair_tracks(
+    app_handle: tauri::AppHandle,
+    track_ids: Vec<String>,
+    state: State<'_, AppState>,
+) -> Result<(), String> {
+    let access_token = get_access_token(&state).await?;
+    let user_id = get_user_id(&state).await?;
+
+    state
+        .music_manager
+        .repair_tracks(&app_handle, &track_ids, &access_token, &user_id)
         .await
         .map_err(|e| e.to_string())
 }
 
+#[tauri::command]
+async fn download_artist(
+    app_handle: tauri::AppHandle,
+    artist_id: String,
+    state: State<'_, AppState>,
+) -> Result<usize, String> {
+    let music_manager = &state.music_manager;
+
+    let access_token = get_access_token(&state).await?;
+    let user_id = get_user_id(&state).await?;
+    let quality = state.download_settings.lock().unwrap().quality;
+
+    music_manager
+        .download_artist(&app_handle, &artist_id, &access_token, &user_id, quality)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn get_download_rules(state: State<'_, AppState>) -> Result<Vec<DownloadRuleResponse>, String> {
+    state
+        .music_manager
+        .get_download_rules()
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn add_download_rule(
+    criteria: RuleCriteria,
+    remove_unmatched: bool,
+    state: State<'_, AppState>,
+) -> Result<DownloadRuleResponse, String> {
+    state
+        .music_manager
+        .add_download_rule(criteria, remove_unmatched)
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn delete_download_rule(rule_id: i32, state: State<'_, AppState>) -> Result<(), String> {
+    state
+        .music_manager
+        .delete_download_rule(rule_id)
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn sync_download_rules(
+    app_handle: tauri::AppHandle,
+    state: State<'_, AppState>,
+) -> Result<RuleSyncSummary, String> {
+    let access_token = get_access_token(&state).await?;
+    let user_id = get_user_id(&state).await?;
+    let quality = state.download_settings.lock().unwrap().quality;
+
+    state
+        .music_manager
+        .sync_download_rules(&app_handle, &access_token, &user_id, quality)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn refresh_album(
+    album_id: String,
+    app_handle: tauri::AppHandle,
+    state: State<'_, AppState>,
+) -> Result<AlbumRefresh, String> {
+    let access_token = get_access_token(&state).await?;
+    let user_id = get_user_id(&state).await?;
+
+    state
+        .music_manager
+        .refresh_album(&app_handle, &album_id, &access_token, &user_id)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn get_download_queue(state: State<'_, AppState>) -> Result<DownloadQueueResponse, String> {
+    state
+        .music_manager
+        .get_download_queue()
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn move_download_to_front(album_id: String, state: State<'_, AppState>) -> Result<(), String> {
+    state
+        .music_manager
+        .move_download_to_front(&album_id)
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn pause_download_queue(state: State<'_, AppState>) {
+    state.music_manager.pause_downloads();
+}
+
+#[tauri::command]
+fn resume_download_queue(state: State<'_, AppState>) {
+    state.music_manager.resume_paused_downloads();
+}
+
+#[tauri::command]
+fn retry_download(
+    app_handle: tauri::AppHandle,
+    album_id: String,
+    state: State<'_, AppState>,
+) -> Result<(), String> {
+    state
+        .music_manager
+        .retry_download(&app_handle, &album_id)
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn cancel_download(
+    app_handle: tauri::AppHandle,
+    album_id: String,
+    state: State<'_, AppState>,
+) -> Result<(), String> {
+    state
+        .music_manager
+        .cancel_download(&app_handle, &album_id)
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn cancel_all_downloads(
+    app_handle: tauri::AppHandle,
+    state: State<'_, AppState>,
+) -> Result<(), String> {
+    state
+        .music_manager
+        .cancel_all_downloads(&app_handle)
+        .map_err(|e| e.to_string())
+}
+
 #[tauri::command]
 async fn delete_album(album_id: String, state: State<'_, AppState>) -> Result<(), String> {
     let music_manager = &state.music_manager;
@@ -166,16 +414,146 @@ async fn delete_album(album_id: String, state: State<'_, AppState>) -> Result<()
 #[tauri::command]
 async fn get_album_info(
     album_id: String,
+    online: Option<bool>,
     state: State<'_, AppState>,
 ) -> Result<AlbumInfoResponse, String> {
     let music_manager = &state.music_manager;
 
+    // albums that aren't downloaded are streamed from the server
+    let downloaded = music_manager
+        .is_album_downloaded(&album_id)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    if online.unwrap_or(false) && !downloaded {
+        let access_token = get_access_token(&state).await?;
+        let user_id = get_user_id(&state).await?;
+
+        return music_manager
+            .get_album_info_online(&album_id, &access_token, Some(user_id.as_str()))
+            .await
+            .map_err(|e| e.to_string());
+    }
+
     music_manager
         .get_album_info(&album_id)
         .await
         .map_err(|e| e.to_string())
 }
 
+#[tauri::command]
+fn get_download_settings(state: State<'_, AppState>) -> DownloadSettings {
+    state.download_settings.lock().unwrap().clone()
+}
+
+#[tauri::command]
+async fn set_download_settings(
+    app_handle: tauri::AppHandle,
+    settings: DownloadSettings,
+    state: State<'_, AppState>,
+) -> Result<(), String> {
+    settings.quality.validate()?;
+
+    let store = app_handle
+        .store("store.json")
+        .map_err(|e| format!("Failed to access store: {}", e))?;
+
+    store.set(DOWNLOAD_SETTINGS_KEY, json!(settings));
+
+    state
+        .download_queue
+        .set_bandwidth_limit(settings.max_bytes_per_second);
+
+    let library_quota_bytes = settings.library_quota_bytes;
+    *state.download_settings.lock().unwrap() = settings;
+
+    // a lower quota takes effect straight away
+    if let Some(quota) = library_quota_bytes {
+        state
+            .music_manager
+            .enforce_library_quota(&app_handle, quota, 0)
+            .await
+            .map_err(|e| e.to_string())?;
+    }
+
+    Ok(())
+}
+
+#[tauri::command]
+fn mark_album_played(album_id: String, state: State<'_, AppState>) -> Result<(), String> {
+    state
+        .music_manager
+        .mark_album_played(&album_id)
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn set_album_pinned(
+    album_id: String,
+    pinned: bool,
+    state: State<'_, AppState>,
+) -> Result<(), String> {
+    state
+        .music_manager
+        .set_album_pinned(&album_id, pinned)
+        .map_err(|e| e.to_string())
+}
+
+// serves thumbnail://localhost/<item id>/<image tag> from the image cache,
+// which fetches the image from the server the first time it's asked for
+async fn thumbnail_response(
+    app_handle: &tauri::AppHandle,
+    request: &http::Request<Vec<u8>>,
+) -> http::Response<Vec<u8>> {
+    let status = |status: http::StatusCode| {
+        http::Response::builder()
+            .status(status)
+            .body(Vec::new())
+            .unwrap()
+    };
+
+    let state = app_handle.state::<AppState>();
+
+    let Some((item_id, tag)) = request.uri().path().trim_start_matches('/').split_once('/') else {
+        return status(http::StatusCode::BAD_REQUEST);
+    };
+
+    let Ok(access_token) = get_access_token(&state).await else {
+        return status(http::StatusCode::UNAUTHORIZED);
+    };
+
+    let path = match state
+        .music_manager
+        .get_thumbnail(item_id, tag, &access_token)
+        .await
+    {
+        Ok(Some(path)) => path,
+        Ok(None) => return status(http::StatusCode::NOT_FOUND),
+        Err(e) => {
+            eprintln!("Failed to get thumbnail for {}: {}", item_id, e);
+            return status(http::StatusCode::BAD_GATEWAY);
+        }
+    };
+
+    let content_type = match path.extension().and_then(|extension| extension.to_str()) {
+        Some("png") => "image/png",
+        Some("webp") => "image/webp",
+        Some("gif") => "image/gif",
+        _ => "image/jpeg",
+    };
+
+    match tokio::fs::read(&path).await {
+        Ok(bytes) => http::Response::builder()
+            .header(http::header::CONTENT_TYPE, content_type)
+            .body(bytes)
+            .unwrap(),
+        Err(e) => {
+            eprintln!("Failed to read thumbnail {}: {}", path.display(), e);
+            status(http::StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
     let device_id = Uuid::new_v4().to_string();
@@ -198,6 +576,10 @@ pub fn run() {
                     .and_then(|v| v.as_str().map(String::from)),
             ));
 
+            let download_settings = Arc::new(Mutex::new(DownloadSettings::from_store_value(
+                store.get(DOWNLOAD_SETTINGS_KEY),
+            )));
+
             // db/repository initialization
             let app_handle = app.handle();
             let app_data_path = app_handle.path().app_data_dir()?;
@@ -216,35 +598,102 @@ pub fn run() {
             db_connection.run_pending_migrations(MIGRATIONS).unwrap();
 
             let db_pool = db::establish_connection();
-            let repository = Repository::new(db_pool);
+            let repository = Repository::new(db_pool.clone());
 
-            // Jellyfin client initialization
+            // browse, search and item responses, so pages load fast and can still
+            // be shown when the server is unreachable
+            let response_cache =
+                ResponseCache::new(db_pool, Duration::minutes(RESPONSE_CACHE_TTL_MINUTES));
+
+            if let Err(e) = response_cache.prune(Duration::days(RESPONSE_CACHE_MAX_AGE_DAYS)) {
+                eprintln!("Failed to prune response cache: {}", e);
+            }
+
+            // Jellyfin client initialization. the csp's media-src in
+            // tauri.conf.json allows streaming from this origin only
             let jellyfin_client = JellyfinClient::new(
                 "http://192.168.1.153:8097".to_string(),
                 "Hacksawdio".to_string(),
                 "Hacksawdio Desktop Client".to_string(),
                 device_id,
                 "0.0.1".to_string(),
+                response_cache,
             );
 
             let (download_queue, download_receiver) = DownloadQueue::new();
+            download_queue
+                .set_bandwidth_limit(download_settings.lock().unwrap().max_bytes_per_second);
+
+            // artwork for online results is cached on disk, outside the downloads
+            let image_cache = ImageCache::new(
+                app_handle.path().app_cache_dir()?.join("artwork"),
+                IMAGE_CACHE_MAX_BYTES,
+            );
 
             let music_manager = Arc::new(MusicManager::new(
                 jellyfin_client,
                 repository,
                 download_queue.clone(),
+                image_cache,
             ));
 
+            // anything still queued when the app last closed
+            if let Err(e) = music_manager.resume_downloads(app.handle()) {
+                eprintln!("Failed to resume downloads: {}", e);
+            }
+
             let app_handle = app.handle().clone();
             let music_manager_clone = music_manager.clone();
+            let download_queue_clone = download_queue.clone();
             let auth_token_clone = auth_token.clone();
+            let download_settings_clone = download_settings.clone();
 
             thread::spawn(move || {
                 process_downloads(
                     app_handle,
                     download_receiver,
                     music_manager_clone,
+                    download_queue_clone,
                     auth_token_clone,
+                    download_settings_clone,
+                );
+            });
+
+            let app_handle = app.handle().clone();
+            let music_manager_clone = music_manager.clone();
+            let auth_token_clone = auth_token.clone();
+
+            thread::spawn(move || {
+                run_remote_control(app_handle, music_manager_clone, auth_token_clone);
+            });
+
+            let app_handle = app.handle().clone();
+            let music_manager_clone = music_manager.clone();
+            let auth_token_clone = auth_token.clone();
+            let user_id_clone = user_id.clone();
+            let download_settings_clone = download_settings.clone();
+
+            thread::spawn(move || {
+                run_rule_sync(
+                    app_handle,
+                    music_manager_clone,
+                    auth_token_clone,
+                    user_id_clone,
+                    download_settings_clone,
+                );
+            });
+
+            let app_handle = app.handle().clone();
+            let music_manager_clone = music_manager.clone();
+            let auth_token_clone = auth_token.clone();
+            let user_id_clone = user_id.clone();
+
+            thread::spawn(move || {
+                run_album_refresh(
+                    app_handle,
+                    music_manager_clone,
+                    auth_token_clone,
+                    user_id_clone,
                 );
             });
 
@@ -252,6 +701,7 @@ pub fn run() {
                 music_manager,
                 auth_token,
                 user_id,
+                download_settings,
                 download_queue,
             });
 
@@ -265,13 +715,41 @@ pub fn run() {
             _ => {}
         })
         .plugin(tauri_plugin_opener::init())
+        .register_asynchronous_uri_scheme_protocol("thumbnail", |ctx, request, responder| {
+            let app_handle = ctx.app_handle().clone();
+
+            tauri::async_runtime::spawn(async move {
+                responder.respond(thumbnail_response(&app_handle, &request).await);
+            });
+        })
         .invoke_handler(tauri::generate_handler![
             authenticate_user_by_name_cmd,
+            authenticate_with_api_key_cmd,
             get_session,
             search_albums,
             download_album,
+            download_tracks,
+            download_artist,
+            get_download_rules,
+            add_download_rule,
+            delete_download_rule,
+            sync_download_rules,
+            refresh_album,
+            get_download_queue,
+            move_download_to_front,
+            pause_download_queue,
+            resume_download_queue,
+            retry_download,
+            verify_library,
+            repair_tracks,
+            cancel_download,
+            cancel_all_downloads,
             delete_album,
+            mark_album_played,
+            set_album_pinned,
             get_album_info,
+            get_download_settings,
+            set_download_settings,
         ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
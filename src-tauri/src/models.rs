@@ -1,4 +1,4 @@
-use crate::schema::{albums, tracks};
+use crate::schema::{albums, download_jobs, download_rules, response_cache, tracks};
 use chrono::NaiveDateTime;
 use diesel::prelude::*;
 
@@ -15,6 +15,25 @@ pub struct Album {
     pub image_path: Option<String>,
     pub created_at: NaiveDateTime,
     pub updated_at: NaiveDateTime,
+    pub quality: String,
+    pub outdated: Option<String>,
+    pub synced_at: Option<NaiveDateTime>,
+    pub production_year: Option<i32>,
+    pub genres: Option<String>,
+    pub artists: Option<String>,
+    pub run_time_ticks: Option<i64>,
+    pub overview: Option<String>,
+    pub date_created: Option<NaiveDateTime>,
+    pub is_favorite: bool,
+    pub play_count: i32,
+    pub back_image_path: Option<String>,
+    pub disc_image_path: Option<String>,
+    pub artist_image_path: Option<String>,
+    // when the quota needs room, unpinned albums go least recently played first
+    pub last_played_at: Option<NaiveDateTime>,
+    pub pinned: bool,
+    // set when a download rule fetched the album rather than the user
+    pub download_rule_id: Option<i32>,
 }
 
 #[derive(Insertable)]
@@ -26,6 +45,53 @@ pub struct NewAlbum<'a> {
     pub image_id: Option<&'a str>,
     pub created_at: NaiveDateTime,
     pub updated_at: NaiveDateTime,
+    pub production_year: Option<i32>,
+    pub genres: Option<String>,
+    pub artists: Option<String>,
+    pub run_time_ticks: Option<i64>,
+    pub overview: Option<&'a str>,
+    pub date_created: Option<NaiveDateTime>,
+    pub is_favorite: bool,
+    pub play_count: i32,
+}
+
+// what a refresh takes from the server's copy of an album
+#[derive(AsChangeset, Debug)]
+#[diesel(table_name = albums, treat_none_as_null = true)]
+pub struct AlbumMetadata<'a> {
+    pub title: &'a str,
+    pub artist: &'a str,
+    pub image_id: Option<&'a str>,
+    pub production_year: Option<i32>,
+    pub genres: Option<String>,
+    pub artists: Option<String>,
+    pub run_time_ticks: Option<i64>,
+    pub overview: Option<&'a str>,
+    pub is_favorite: bool,
+    pub play_count: i32,
+}
+
+// local paths of the artwork saved with an album
+#[derive(AsChangeset, Debug, Default)]
+#[diesel(table_name = albums, treat_none_as_null = true)]
+pub struct AlbumArtwork {
+    pub image_path: Option<String>,
+    pub back_image_path: Option<String>,
+    pub disc_image_path: Option<String>,
+    pub artist_image_path: Option<String>,
+}
+
+impl AlbumArtwork {
+    pub fn paths(&self) -> impl Iterator<Item = &str> {
+        [
+            &self.image_path,
+            &self.back_image_path,
+            &self.disc_image_path,
+            &self.artist_image_path,
+        ]
+        .into_iter()
+        .filter_map(|path| path.as_deref())
+    }
 }
 
 #[derive(Identifiable, Queryable, Selectable, Associations, Debug, PartialEq)]
@@ -37,8 +103,19 @@ pub struct Track {
     pub jellyfin_id: String,
     pub name: String,
     pub album_id: i32,
+    // not set for tracks left out of a partial download
     pub path: Option<String>,
     pub track_index: Option<i32>,
+    pub disc_number: Option<i32>,
+    pub artists: Option<String>,
+    pub run_time_ticks: Option<i64>,
+    pub is_favorite: bool,
+    pub play_count: i32,
+    // the file's size in bytes and sha256 when it downloaded, to check it later
+    pub size: Option<i64>,
+    pub sha256: Option<String>,
+    // when the server last saved the track, to tell if it changed since
+    pub date_modified: Option<NaiveDateTime>,
 }
 
 #[derive(Insertable)]
@@ -49,4 +126,74 @@ pub struct NewTrack<'a> {
     pub album_id: i32,
     pub path: Option<String>,
     pub track_index: i32,
+    pub disc_number: Option<i32>,
+    pub artists: Option<String>,
+    pub run_time_ticks: Option<i64>,
+    pub is_favorite: bool,
+    pub play_count: i32,
+    pub size: Option<i64>,
+    pub sha256: Option<String>,
+    pub date_modified: Option<NaiveDateTime>,
+}
+
+#[derive(Queryable, Selectable, Insertable, Debug)]
+#[diesel(table_name = response_cache)]
+#[diesel(check_for_backend(diesel::sqlite::Sqlite))]
+pub struct CachedResponse {
+    pub cache_key: String,
+    pub body: String,
+    pub etag: Option<String>,
+    pub fetched_at: NaiveDateTime,
+}
+
+// download job states. failed jobs keep their files and can be retried, done
+// jobs are kept until the next start so the queue can show what finished
+pub const JOB_QUEUED: &str = "queued";
+pub const JOB_FAILED: &str = "failed";
+pub const JOB_DONE: &str = "done";
+
+#[derive(Queryable, Selectable, Debug)]
+#[diesel(table_name = download_jobs)]
+#[diesel(check_for_backend(diesel::sqlite::Sqlite))]
+pub struct DownloadJob {
+    pub id: i32,
+    pub album_id: String,
+    pub user_id: String,
+    pub quality: String,
+    pub created_at: NaiveDateTime,
+    pub status: String,
+    pub error: Option<String>,
+    pub attempts: i32,
+    // lower runs sooner, jobs moved to the front get one below the rest
+    pub position: i32,
+    // a json list of the tracks to download, or the whole album if not set
+    pub track_ids: Option<String>,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = download_jobs)]
+pub struct NewDownloadJob<'a> {
+    pub album_id: &'a str,
+    pub user_id: &'a str,
+    pub quality: &'a str,
+    pub track_ids: Option<String>,
+}
+
+#[derive(Queryable, Selectable, Debug)]
+#[diesel(table_name = download_rules)]
+#[diesel(check_for_backend(diesel::sqlite::Sqlite))]
+pub struct DownloadRule {
+    pub id: i32,
+    // a json RuleCriteria
+    pub criteria: String,
+    // remove albums this rule downloaded once they stop matching
+    pub remove_unmatched: bool,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = download_rules)]
+pub struct NewDownloadRule<'a> {
+    pub criteria: &'a str,
+    pub remove_unmatched: bool,
 }
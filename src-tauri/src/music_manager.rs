@@ -1,22 +1,54 @@
+use crate::album_refresh::{AlbumRefresh, TrackRename};
+use crate::download_queue::{DownloadJobState, DownloadQueueItem, DownloadQueueResponse};
+use crate::download_rules::{DownloadRuleResponse, RuleCriteria, RuleSyncSummary};
+use crate::image_cache::ImageCache;
+use crate::integrity::{check_track, DamagedTrack, FileDigest, LibraryVerification, TrackCheck};
 use crate::jellyfin::client::JellyfinClient;
 use crate::jellyfin::errors::JellyfinError;
 use crate::jellyfin::models::{
-    AlbumInfoResponse, AlbumSearchResponse, AlbumSearchResponseItem, AlbumTrackResponse,
-    AuthResponse, JellyfinItem, JellyfinItemsResponse,
+    group_discs, ticks_to_seconds, AlbumInfoResponse, AlbumSearchResponse, AlbumSearchResponseItem,
+    AlbumTrackResponse, AuthResponse, ClientCapabilities, DownloadQuality, JellyfinItem,
+    JellyfinItemsResponse, LibraryUpdateInfo,
 };
-use crate::models::Album;
+use crate::models::{
+    Album, AlbumArtwork, AlbumMetadata, DownloadJob, NewAlbum, NewDownloadJob, NewDownloadRule,
+    NewTrack, JOB_DONE, JOB_FAILED, JOB_QUEUED,
+};
+use crate::rate_limit::BandwidthLimiter;
 use crate::repository::Repository;
+use chrono::Utc;
 use reqwest::StatusCode;
 use sanitize_filename::sanitize;
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 use std::fs;
-use std::path::PathBuf;
-use tauri::{AppHandle, Manager};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::AtomicBool;
+use tauri::{AppHandle, Emitter, Manager};
+use url::Url;
+
+pub const OUTDATED_UPDATED: &str = "updated";
+pub const OUTDATED_REMOVED: &str = "removed";
+
+const THUMBNAIL_SIZE: u32 = 300;
+
+#[derive(Clone, serde::Serialize)]
+struct EvictedAlbum {
+    album_id: String,
+    title: String,
+    artist: String,
+    bytes: u64,
+}
+
+#[derive(Clone, serde::Serialize)]
+struct AlbumsEvicted {
+    albums: Vec<EvictedAlbum>,
+}
 
 pub struct MusicManager {
     jellyfin_client: JellyfinClient,
     pub repository: Repository,
     download_queue: crate::download_queue::DownloadQueue,
+    image_cache: ImageCache,
 }
 
 impl MusicManager {
@@ -24,11 +56,13 @@ impl MusicManager {
         jellyfin_client: JellyfinClient,
         repository: Repository,
         download_queue: crate::download_queue::DownloadQueue,
+        image_cache: ImageCache,
     ) -> Self {
         Self {
             jellyfin_client,
             repository,
             download_queue,
+            image_cache,
         }
     }
 
@@ -42,6 +76,16 @@ impl MusicManager {
             .await
     }
 
+    pub async fn authenticate_with_api_key(
+        &self,
+        api_key: &str,
+        user: &str,
+    ) -> Result<AuthResponse, JellyfinError> {
+        self.jellyfin_client
+            .authenticate_with_api_key(api_key, user)
+            .await
+    }
+
     pub async fn search_albums(
         &self,
         search: &str,
@@ -57,7 +101,7 @@ impl MusicManager {
                 .get_recents(access_token, limit, offset, user_id)
                 .await?;
 
-            return self.add_downloaded_state(&recents).await;
+            return self.add_downloaded_state(&recents);
         }
 
         let album_results = self
@@ -69,6 +113,8 @@ impl MusicManager {
             .search_albums_by_artist(search, access_token, user_id)
             .await?;
 
+        let stale = album_results.stale || artist_album_results.stale;
+
         let mut combined_items =
             self.combine_jellyfin_items(album_results.items, artist_album_results.items);
 
@@ -86,9 +132,10 @@ impl MusicManager {
             total_record_count,
             start_index: offset.unwrap_or(0),
             items: paginated_items,
+            stale,
         };
 
-        self.add_downloaded_state(&response).await
+        self.add_downloaded_state(&response)
     }
 
     pub async fn search_albums_offline(
@@ -119,6 +166,7 @@ impl MusicManager {
                 album_artist: album.artist,
                 downloaded: album.path.is_some(),
                 image_url: album.image_path,
+                outdated: album.outdated,
             })
             .collect::<Vec<_>>();
 
@@ -126,287 +174,2009 @@ impl MusicManager {
             total_record_count: items.len() as u32,
             start_index: offset.unwrap_or(0),
             items,
+            stale: false,
         })
     }
 
+    // track_ids picks out tracks to download, or None for the whole album.
+    // asking again while the album is waiting adds to its selection
     pub async fn download_album(
         &self,
         app_handle: &tauri::AppHandle,
         album_id: &str,
+        track_ids: Option<Vec<String>>,
+        access_token: &str,
         user_id: &str,
+        quality: DownloadQuality,
     ) -> Result<(), JellyfinError> {
+        let job = self
+            .repository
+            .find_download_job(album_id)
+            .map_err(|e| JellyfinError::GenericError(e.to_string()))?;
+
+        if let Some(job) = job.filter(|job| job.status == JOB_QUEUED) {
+            let queued_ids = job.track_ids.map(|ids| from_json_list(Some(ids)));
+            let merged_ids = merge_track_selection(queued_ids.clone(), track_ids);
+
+            // nothing that isn't already queued
+            if merged_ids == queued_ids {
+                return Ok(());
+            }
+
+            if self.download_queue.is_active(album_id) {
+                return Err(JellyfinError::GenericError(
+                    "Album is already downloading".to_string(),
+                ));
+            }
+
+            self.repository
+                .set_download_job_tracks(
+                    album_id,
+                    merged_ids.clone().and_then(to_json_list).as_deref(),
+                )
+                .map_err(|e| JellyfinError::GenericError(e.to_string()))?;
+
+            self.download_queue.set_tracks(album_id, merged_ids);
+            return Ok(());
+        }
+
+        // saved up front so the queue can show the title and artist
+        self.sync_album(album_id, access_token, Some(user_id))
+            .await?;
+
+        // downloading again replaces a failed or finished job. the job is saved
+        // before it's queued so it survives a restart
+        self.repository
+            .delete_download_jobs(album_id)
+            .map_err(|e| JellyfinError::GenericError(e.to_string()))?;
+
+        self.repository
+            .create_download_job(&NewDownloadJob {
+                album_id,
+                user_id,
+                quality: &quality.to_string(),
+                track_ids: track_ids.clone().and_then(to_json_list),
+            })
+            .map_err(|e| JellyfinError::GenericError(e.to_string()))?;
+
         self.download_queue.add_album(
             crate::download_queue::Album {
                 album_id: album_id.to_string(),
                 user_id: user_id.to_string(),
+                quality,
+                track_ids,
             },
             app_handle,
         );
         Ok(())
     }
 
-    pub async fn delete_album(&self, album_id: &str) -> Result<(), JellyfinError> {
-        let album = self
+    // queues every album by the album artist that isn't downloaded or queued
+    // already, oldest release first, and returns how many were queued. partial
+    // downloads are queued for the rest of their tracks
+    pub async fn download_artist(
+        &self,
+        app_handle: &tauri::AppHandle,
+        artist_id: &str,
+        access_token: &str,
+        user_id: &str,
+        quality: DownloadQuality,
+    ) -> Result<usize, JellyfinError> {
+        let (albums, _) = self
+            .get_album_artist_albums(&[artist_id.to_string()], access_token, user_id)
+            .await?;
+
+        let downloaded = self
             .repository
-            .find_album(album_id)
-            .map_err(|e| JellyfinError::GenericError(e.to_string()))?
-            .ok_or_else(|| JellyfinError::ApiError {
-                status: StatusCode::NOT_FOUND,
-                message: "Album not found".to_string(),
-            })?;
+            .get_fully_downloaded_album_ids(albums.iter().map(|album| album.id.clone()).collect())
+            .map_err(|e| JellyfinError::GenericError(e.to_string()))?;
 
-        if let Some(album_path) = &album.path {
-            let path_buf = PathBuf::from(album_path);
-            if path_buf.exists() {
-                fs::remove_dir_all(&path_buf).map_err(|e| {
-                    JellyfinError::GenericError(format!("Failed to delete album dir: {}", e))
-                })?;
-            }
+        let mut queued = 0;
 
-            // remove the artist directory if it's now empty
-            let parent_dir = path_buf.parent().ok_or_else(|| {
-                JellyfinError::GenericError("Failed to get parent directory".to_string())
-            })?;
+        for album in albums
+            .iter()
+            .filter(|album| !downloaded.contains(&album.id))
+        {
+            let job = self
+                .repository
+                .find_download_job(&album.id)
+                .map_err(|e| JellyfinError::GenericError(e.to_string()))?;
 
-            if parent_dir.exists() && parent_dir.is_dir() {
-                let entries = fs::read_dir(parent_dir).map_err(|e| {
-                    JellyfinError::GenericError(format!("Failed to read dir: {}", e))
-                })?;
+            if job.is_some_and(|job| job.status == JOB_QUEUED) {
+                continue;
+            }
 
-                if entries.count() == 0 {
-                    fs::remove_dir(parent_dir).map_err(|e| {
-                        JellyfinError::GenericError(format!("Failed to delete parent dir: {}", e))
-                    })?;
-                }
+            // one album failing shouldn't stop the rest of the discography
+            if let Err(e) = self
+                .download_album(app_handle, &album.id, None, access_token, user_id, quality)
+                .await
+            {
+                eprintln!("Error queueing album {} for an artist: {}", album.id, e);
+                continue;
             }
-        }
 
-        self.repository
-            .delete_album_and_tracks(&album)
-            .map_err(|e| JellyfinError::GenericError(e.to_string()))?;
+            queued += 1;
+        }
 
-        Ok(())
+        Ok(queued)
     }
 
-    async fn search_albums_by_artist(
+    // every album by the album artists, oldest release first. a page at a time,
+    // in case a discography is a long one
+    async fn get_album_artist_albums(
         &self,
-        search: &str,
+        artist_ids: &[String],
         access_token: &str,
-        user_id: Option<&str>,
-    ) -> Result<JellyfinItemsResponse, JellyfinError> {
-        let artist_results = self
-            .jellyfin_client
-            .search_album_artists(search, access_token, user_id)
-            .await?;
+        user_id: &str,
+    ) -> Result<(Vec<JellyfinItem>, bool), JellyfinError> {
+        let mut albums = Vec::new();
+        let mut stale = false;
 
-        let album_artist_ids: Vec<String> = artist_results
-            .items
-            .iter()
-            .map(|item| item.id.clone())
-            .collect();
+        loop {
+            let page = self
+                .jellyfin_client
+                .search_albums_by_album_artist(
+                    artist_ids.to_vec(),
+                    Some("ProductionYear,PremiereDate,SortName"),
+                    Some(albums.len() as u32),
+                    access_token,
+                    Some(user_id),
+                )
+                .await?;
 
-        if album_artist_ids.is_empty() {
-            return Ok(JellyfinItemsResponse {
-                total_record_count: 0,
-                start_index: 0,
-                items: Vec::new(),
-            });
-        }
+            let page_len = page.items.len();
+            stale |= page.stale;
+            albums.extend(page.items);
 
-        let artist_album_results = self
-            .jellyfin_client
-            .search_albums_by_album_artist(album_artist_ids, access_token, user_id)
-            .await;
+            if page_len == 0 || albums.len() >= page.total_record_count as usize {
+                break;
+            }
+        }
 
-        artist_album_results
+        Ok((albums, stale))
     }
 
-    async fn add_downloaded_state(
-        &self,
-        res: &JellyfinItemsResponse,
-    ) -> Result<AlbumSearchResponse, JellyfinError> {
-        let album_ids = res
-            .items
-            .iter()
-            .map(|item| item.id.clone())
-            .collect::<Vec<_>>();
-
-        let downloaded_albums = self
+    pub fn get_download_rules(&self) -> Result<Vec<DownloadRuleResponse>, JellyfinError> {
+        let rules = self
             .repository
-            .get_downloaded_album_ids(album_ids)
+            .get_download_rules()
             .map_err(|e| JellyfinError::GenericError(e.to_string()))?;
 
-        let items = res
-            .items
-            .clone()
+        Ok(rules
             .into_iter()
-            .map(|item| AlbumSearchResponseItem {
-                name: item.name,
-                id: item.id.clone(),
-                album_artist: item
-                    .album_artist
-                    .unwrap_or_else(|| "Unknown Artist".to_string()),
-                downloaded: downloaded_albums.contains(&item.id),
-                image_url: None, // not currently showing art for online results
-            })
-            .collect::<Vec<_>>();
-
-        Ok(AlbumSearchResponse {
-            total_record_count: res.total_record_count,
-            start_index: res.start_index,
-            items,
-        })
+            .filter_map(DownloadRuleResponse::from_rule)
+            .collect())
     }
 
-    pub async fn download_track(
+    pub fn add_download_rule(
         &self,
-        track_id: &str,
-        download_path: &str,
-        access_token: &str,
-    ) -> Result<(), JellyfinError> {
-        self.jellyfin_client
-            .download_track(track_id, download_path, access_token)
-            .await
+        criteria: RuleCriteria,
+        remove_unmatched: bool,
+    ) -> Result<DownloadRuleResponse, JellyfinError> {
+        criteria.validate().map_err(JellyfinError::GenericError)?;
+
+        let rule = self
+            .repository
+            .create_download_rule(&NewDownloadRule {
+                criteria: &serde_json::to_string(&criteria)?,
+                remove_unmatched,
+            })
+            .map_err(|e| JellyfinError::GenericError(e.to_string()))?;
+
+        Ok(DownloadRuleResponse {
+            id: rule.id,
+            criteria,
+            remove_unmatched: rule.remove_unmatched,
+        })
     }
 
-    pub async fn download_album_art(
-        &self,
-        album_id: &str,
-        image_id: &str,
-        download_path: &str,
-        access_token: &str,
-    ) -> Result<(), JellyfinError> {
-        self.jellyfin_client
-            .download_album_art(album_id, image_id, download_path, access_token)
-            .await
+    pub fn delete_download_rule(&self, rule_id: i32) -> Result<(), JellyfinError> {
+        self.repository
+            .delete_download_rule(rule_id)
+            .map_err(|e| JellyfinError::GenericError(e.to_string()))
     }
 
-    pub async fn get_tracks(
+    // the albums a rule matches right now, with the tracks it wants from each
+    // (None for all of them), and whether any of it came from the cache
+    // because the server couldn't be reached
+    async fn resolve_download_rule(
         &self,
-        album_id: &str,
+        criteria: &RuleCriteria,
         access_token: &str,
-    ) -> Result<JellyfinItemsResponse, JellyfinError> {
-        self.jellyfin_client
-            .get_tracks(album_id, access_token)
-            .await
-    }
-
-    pub async fn get_album_info(&self, album_id: &str) -> Result<AlbumInfoResponse, JellyfinError> {
-        let (local_album, local_tracks) = self
-            .repository
-            .get_album_details(album_id)
-            .map_err(|e| JellyfinError::GenericError(e.to_string()))?
-            .ok_or_else(|| JellyfinError::ApiError {
-                status: StatusCode::NOT_FOUND,
-                message: "Album not found".to_string(),
-            })?;
+        user_id: &str,
+    ) -> Result<(Vec<(String, Option<Vec<String>>)>, bool), JellyfinError> {
+        let (albums, stale) = match criteria {
+            RuleCriteria::Favorites => {
+                let albums = self
+                    .jellyfin_client
+                    .get_favorite_albums(access_token, user_id)
+                    .await?;
+                (albums.items, albums.stale)
+            }
+            RuleCriteria::RecentlyAdded { count } => {
+                let albums = self
+                    .jellyfin_client
+                    .get_recents(access_token, Some(*count), None, Some(user_id))
+                    .await?;
+                (albums.items, albums.stale)
+            }
+            RuleCriteria::Artists { artist_ids } => {
+                self.get_album_artist_albums(artist_ids, access_token, user_id)
+                    .await?
+            }
+            RuleCriteria::Playlist { playlist_id } => {
+                let tracks = self
+                    .jellyfin_client
+                    .get_playlist_items(playlist_id, access_token, user_id)
+                    .await?;
+
+                // the playlist's tracks, grouped by album in playlist order
+                let mut albums: Vec<(String, Option<Vec<String>>)> = Vec::new();
+
+                for track in tracks.items {
+                    let Some(album_id) = track.album_id else {
+                        continue;
+                    };
+
+                    match albums.iter_mut().find(|(id, _)| *id == album_id) {
+                        Some((_, Some(track_ids))) => track_ids.push(track.id),
+                        _ => albums.push((album_id, Some(vec![track.id]))),
+                    }
+                }
 
-        let result = AlbumInfoResponse {
-            name: local_album.title,
-            artist: local_album.artist,
-            tracks: local_tracks
-                .into_iter()
-                .map(|track| AlbumTrackResponse {
-                    name: track.name,
-                    playback_url: track.path.unwrap_or_default(),
-                })
-                .collect(),
-            image_url: local_album.image_path,
+                return Ok((albums, tracks.stale));
+            }
         };
 
-        Ok(result)
+        Ok((
+            albums.into_iter().map(|album| (album.id, None)).collect(),
+            stale,
+        ))
     }
 
-    pub async fn sync_album(
+    // queues whatever the rules match that isn't downloaded yet, then removes
+    // albums that rules with remove_unmatched downloaded and no rule matches
+    // any more. nothing is removed if the server couldn't be reached, since the
+    // matches could be out of date
+    pub async fn sync_download_rules(
         &self,
-        album_id: &str,
+        app_handle: &tauri::AppHandle,
         access_token: &str,
-        user_id: Option<&str>,
-    ) -> Result<Album, JellyfinError> {
-        // Check if the album already exists in the database
-        if let Some(album) = self
-            .repository
-            .find_album(album_id)
-            .map_err(|e| JellyfinError::GenericError(e.to_string()))?
-        {
-            return Ok(album);
+        user_id: &str,
+        quality: DownloadQuality,
+    ) -> Result<RuleSyncSummary, JellyfinError> {
+        let rules = self.get_download_rules()?;
+
+        // each album once, claimed by the first rule to match it
+        let mut matches: Vec<(String, i32, Option<Vec<String>>)> = Vec::new();
+        let mut matched: HashMap<String, usize> = HashMap::new();
+        let mut stale = false;
+
+        for rule in &rules {
+            let (rule_matches, rule_stale) = self
+                .resolve_download_rule(&rule.criteria, access_token, user_id)
+                .await?;
+            stale |= rule_stale;
+
+            for (album_id, track_ids) in rule_matches {
+                match matched.get(&album_id) {
+                    Some(&index) => {
+                        let selection = matches[index].2.take();
+                        matches[index].2 = merge_track_selection(selection, track_ids);
+                    }
+                    None => {
+                        matched.insert(album_id.clone(), matches.len());
+                        matches.push((album_id, rule.id, track_ids));
+                    }
+                }
+            }
         }
 
-        // album does not exist, we will insert it
-        let album_info = self
-            .jellyfin_client
-            .get_jellyfin_item(album_id, access_token, user_id)
-            .await?;
+        let mut queued = 0;
 
-        let image_id = album_info
-            .image_tags
-            .as_ref()
-            .and_then(|tags| tags.primary.as_deref());
+        for (album_id, rule_id, track_ids) in matches {
+            let job = self
+                .repository
+                .find_download_job(&album_id)
+                .map_err(|e| JellyfinError::GenericError(e.to_string()))?;
 
-        self.repository
-            .create_album(
-                album_id,
-                &album_info.name,
-                &album_info
-                    .album_artist
-                    .unwrap_or_else(|| "Unknown Artist".to_string()),
-                image_id,
-            )
-            .map_err(|e| JellyfinError::GenericError(e.to_string()))
-    }
+            // waiting already, or failed and waiting for the user to retry
+            if job.is_some_and(|job| job.status != JOB_DONE) {
+                continue;
+            }
 
-    pub fn create_album_dir(
-        &self,
-        app_handle: &AppHandle,
-        album_artist: &str,
-        album_name: &str,
-    ) -> Result<PathBuf, JellyfinError> {
-        let mut app_data_path = app_handle.path().app_data_dir().map_err(|e| {
-            JellyfinError::GenericError(format!("Failed to get app data dir: {}", e))
-        })?;
+            let local = self
+                .repository
+                .get_album_details(&album_id)
+                .map_err(|e| JellyfinError::GenericError(e.to_string()))?;
+
+            let downloaded = local
+                .as_ref()
+                .is_some_and(|(album, _)| album.path.is_some());
+
+            // a partial download leaves the tracks it skipped without a path
+            let has_tracks = local.as_ref().is_some_and(|(album, tracks)| {
+                album.path.is_some()
+                    && tracks.iter().all(|track| {
+                        track.path.is_some()
+                            || track_ids
+                                .as_ref()
+                                .is_some_and(|ids| !ids.contains(&track.jellyfin_id))
+                    })
+            });
 
-        app_data_path.push("downloads");
-        app_data_path.push(sanitize(album_artist));
-        app_data_path.push(sanitize(album_name));
+            if has_tracks {
+                continue;
+            }
 
-        if !app_data_path.exists() {
-            fs::create_dir_all(&app_data_path).map_err(|e| {
-                JellyfinError::GenericError(format!("Failed to create album directory: {}", e))
-            })?;
-        }
+            if let Err(e) = self
+                .download_album(
+                    app_handle,
+                    &album_id,
+                    track_ids,
+                    access_token,
+                    user_id,
+                    quality,
+                )
+                .await
+            {
+                eprintln!("Error queueing album {} for a rule: {}", album_id, e);
+                continue;
+            }
 
-        Ok(app_data_path)
-    }
+            // albums the user downloaded themselves are never removed by a rule
+            if !downloaded {
+                self.repository
+                    .set_album_download_rule(&album_id, rule_id)
+                    .map_err(|e| JellyfinError::GenericError(e.to_string()))?;
+            }
 
-    pub fn generate_track_name(&self, track: &JellyfinItem, total_tracks: usize) -> String {
-        let extension = match track.container.as_ref() {
-            Some(ext) => format!(".{}", ext),
-            None => "".to_string(),
-        };
+            queued += 1;
+        }
 
-        let width = if total_tracks == 0 {
-            2
-        } else {
-            total_tracks.to_string().len()
-        };
+        let mut removed = Vec::new();
 
-        let track_number = format!("{:0width$}", track.index_number.unwrap_or(0), width = width);
+        let removing_rules = rules
+            .iter()
+            .filter(|rule| rule.remove_unmatched)
+            .map(|rule| rule.id)
+            .collect::<HashSet<_>>();
+
+        if !stale && !removing_rules.is_empty() {
+            let albums = self
+                .repository
+                .get_rule_downloaded_albums()
+                .map_err(|e| JellyfinError::GenericError(e.to_string()))?;
+
+            for album in albums {
+                let removable = album
+                    .download_rule_id
+                    .is_some_and(|rule_id| removing_rules.contains(&rule_id));
+
+                if removable && !matched.contains_key(&album.jellyfin_id) {
+                    self.delete_album(&album.jellyfin_id).await?;
+                    removed.push(album.jellyfin_id);
+                }
+            }
+        }
 
-        format!("{} - {}{}", track_number, sanitize(&track.name), extension)
+        Ok(RuleSyncSummary { queued, removed })
     }
 
-    fn combine_jellyfin_items(
+    pub fn cancel_download(
         &self,
-        list1: Vec<JellyfinItem>,
-        list2: Vec<JellyfinItem>,
-    ) -> Vec<JellyfinItem> {
-        let mut unique_items: HashSet<JellyfinItem> = HashSet::new();
+        app_handle: &tauri::AppHandle,
+        album_id: &str,
+    ) -> Result<(), JellyfinError> {
+        self.repository
+            .delete_download_jobs(album_id)
+            .map_err(|e| JellyfinError::GenericError(e.to_string()))?;
 
-        for item in list1 {
-            unique_items.insert(item);
+        // the processor cleans up an album that's downloading. one that was
+        // still waiting, or had failed, is cleaned up here
+        if self.download_queue.is_active(album_id) {
+            self.download_queue.cancel(album_id, app_handle);
+            return Ok(());
         }
 
-        for item in list2 {
+        self.download_queue.cancel(album_id, app_handle);
+
+        let album = self
+            .repository
+            .find_album(album_id)
+            .map_err(|e| JellyfinError::GenericError(e.to_string()))?;
+
+        if let Some(album) = album.filter(|album| album.path.is_none()) {
+            let dir = self.album_dir(app_handle, &album.artist, &album.title)?;
+            self.discard_partial_album(album_id, Some(&dir))?;
+        }
+
+        Ok(())
+    }
+
+    pub fn cancel_all_downloads(&self, app_handle: &tauri::AppHandle) -> Result<(), JellyfinError> {
+        self.repository
+            .delete_all_download_jobs()
+            .map_err(|e| JellyfinError::GenericError(e.to_string()))?;
+
+        self.download_queue.cancel_all(app_handle);
+        Ok(())
+    }
+
+    // picks up where the last run left off. queued jobs are queued again, and
+    // albums a crash left half done with no job to finish them are cleaned up.
+    // failed jobs wait to be retried, and finished ones are forgotten
+    pub fn resume_downloads(&self, app_handle: &tauri::AppHandle) -> Result<(), JellyfinError> {
+        self.repository
+            .delete_finished_download_jobs()
+            .map_err(|e| JellyfinError::GenericError(e.to_string()))?;
+
+        let jobs = self
+            .repository
+            .get_download_jobs()
+            .map_err(|e| JellyfinError::GenericError(e.to_string()))?;
+
+        let queued = jobs
+            .iter()
+            .map(|job| job.album_id.as_str())
+            .collect::<HashSet<_>>();
+
+        let incomplete = self
+            .repository
+            .get_incomplete_albums()
+            .map_err(|e| JellyfinError::GenericError(e.to_string()))?;
+
+        for album in incomplete
+            .iter()
+            .filter(|album| !queued.contains(album.jellyfin_id.as_str()))
+        {
+            let dir = self.album_dir(app_handle, &album.artist, &album.title)?;
+            self.discard_partial_album(&album.jellyfin_id, Some(&dir))?;
+        }
+
+        for job in jobs.into_iter().filter(|job| job.status == JOB_QUEUED) {
+            self.queue_download_job(app_handle, job);
+        }
+
+        Ok(())
+    }
+
+    // puts a failed download back in the queue by hand
+    pub fn retry_download(
+        &self,
+        app_handle: &tauri::AppHandle,
+        album_id: &str,
+    ) -> Result<(), JellyfinError> {
+        self.repository
+            .reset_download_job(album_id)
+            .map_err(|e| JellyfinError::GenericError(e.to_string()))?;
+
+        self.requeue_download_job(app_handle, album_id)
+    }
+
+    // queues a saved job again, unless it was cancelled or given up on since
+    pub fn requeue_download_job(
+        &self,
+        app_handle: &tauri::AppHandle,
+        album_id: &str,
+    ) -> Result<(), JellyfinError> {
+        let job = self
+            .repository
+            .find_download_job(album_id)
+            .map_err(|e| JellyfinError::GenericError(e.to_string()))?;
+
+        if let Some(job) = job.filter(|job| job.status == JOB_QUEUED) {
+            self.queue_download_job(app_handle, job);
+        }
+
+        Ok(())
+    }
+
+    fn queue_download_job(&self, app_handle: &tauri::AppHandle, job: DownloadJob) {
+        self.download_queue.add_album(
+            crate::download_queue::Album {
+                album_id: job.album_id,
+                user_id: job.user_id,
+                quality: job.quality.parse().unwrap_or_default(),
+                track_ids: job.track_ids.map(|ids| from_json_list(Some(ids))),
+            },
+            app_handle,
+        );
+    }
+
+    pub fn finish_download_job(&self, album_id: &str) -> Result<(), JellyfinError> {
+        self.repository
+            .set_download_job_status(album_id, JOB_DONE, None)
+            .map_err(|e| JellyfinError::GenericError(e.to_string()))
+    }
+
+    // every job in queue order: what's downloading, then what's waiting, then
+    // what failed or finished
+    pub fn get_download_queue(&self) -> Result<DownloadQueueResponse, JellyfinError> {
+        let jobs = self
+            .repository
+            .get_download_jobs()
+            .map_err(|e| JellyfinError::GenericError(e.to_string()))?;
+
+        let mut items = jobs
+            .into_iter()
+            .map(|job| {
+                let album = self
+                    .repository
+                    .find_album(&job.album_id)
+                    .map_err(|e| JellyfinError::GenericError(e.to_string()))?;
+
+                let state = if self.download_queue.is_active(&job.album_id) {
+                    DownloadJobState::Active
+                } else {
+                    match job.status.as_str() {
+                        JOB_FAILED => DownloadJobState::Failed,
+                        JOB_DONE => DownloadJobState::Done,
+                        _ => DownloadJobState::Queued,
+                    }
+                };
+
+                Ok(DownloadQueueItem {
+                    title: album.as_ref().map(|album| album.title.clone()),
+                    artist: album.as_ref().map(|album| album.artist.clone()),
+                    album_id: job.album_id,
+                    state,
+                    error: job.error,
+                    attempts: job.attempts,
+                })
+            })
+            .collect::<Result<Vec<_>, JellyfinError>>()?;
+
+        // stable, so each state keeps the queue order
+        items.sort_by_key(|item| item.state);
+
+        Ok(DownloadQueueResponse {
+            paused: self.download_queue.is_paused(),
+            items,
+        })
+    }
+
+    pub fn move_download_to_front(&self, album_id: &str) -> Result<(), JellyfinError> {
+        self.repository
+            .move_download_job_to_front(album_id)
+            .map_err(|e| JellyfinError::GenericError(e.to_string()))?;
+
+        self.download_queue.move_to_front(album_id);
+        Ok(())
+    }
+
+    pub fn pause_downloads(&self) {
+        self.download_queue.pause();
+    }
+
+    pub fn resume_paused_downloads(&self) {
+        self.download_queue.resume();
+    }
+
+    pub async fn delete_album(&self, album_id: &str) -> Result<(), JellyfinError> {
+        let album = self
+            .repository
+            .find_album(album_id)
+            .map_err(|e| JellyfinError::GenericError(e.to_string()))?
+            .ok_or_else(|| JellyfinError::ApiError {
+                status: StatusCode::NOT_FOUND,
+                message: "Album not found".to_string(),
+            })?;
+
+        if let Some(album_path) = &album.path {
+            self.remove_album_dir(Path::new(album_path))?;
+        }
+
+        self.repository
+            .delete_album_and_tracks(&album)
+            .map_err(|e| JellyfinError::GenericError(e.to_string()))?;
+
+        Ok(())
+    }
+
+    // cleans up after a download that didn't finish. the album row may have no
+    // path yet, so the directory it was downloading into is passed in. an album
+    // already in the library keeps its files, and only loses what the download
+    // added to them
+    pub fn discard_partial_album(
+        &self,
+        album_id: &str,
+        album_dir: Option<&Path>,
+    ) -> Result<(), JellyfinError> {
+        let album = self
+            .repository
+            .find_album(album_id)
+            .map_err(|e| JellyfinError::GenericError(e.to_string()))?;
+
+        match album {
+            Some(album) if album.path.is_some() => {
+                if let Some(album_dir) = album_dir {
+                    self.remove_unsaved_files(&album, album_dir)?;
+                }
+            }
+            album => {
+                if let Some(album_dir) = album_dir {
+                    self.remove_album_dir(album_dir)?;
+                }
+
+                if let Some(album) = album {
+                    self.repository
+                        .delete_album_and_tracks(&album)
+                        .map_err(|e| JellyfinError::GenericError(e.to_string()))?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    // removes the files in an album's directory that none of its tracks or
+    // artwork were saved at, like .part files and tracks that never finished
+    fn remove_unsaved_files(&self, album: &Album, album_dir: &Path) -> Result<(), JellyfinError> {
+        let tracks = self
+            .repository
+            .get_album_details(&album.jellyfin_id)
+            .map_err(|e| JellyfinError::GenericError(e.to_string()))?
+            .map(|(_, tracks)| tracks)
+            .unwrap_or_default();
+
+        let saved = tracks
+            .iter()
+            .map(|track| &track.path)
+            .chain([
+                &album.image_path,
+                &album.back_image_path,
+                &album.disc_image_path,
+                &album.artist_image_path,
+            ])
+            .filter_map(|path| path.as_deref().map(PathBuf::from))
+            .collect::<HashSet<_>>();
+
+        let entries = fs::read_dir(album_dir)
+            .map_err(|e| JellyfinError::GenericError(format!("Failed to read dir: {}", e)))?;
+
+        for entry in entries.filter_map(|entry| entry.ok()) {
+            let path = entry.path();
+
+            if path.is_file() && !saved.contains(&path) {
+                fs::remove_file(&path).map_err(|e| {
+                    JellyfinError::GenericError(format!("Failed to delete file: {}", e))
+                })?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn remove_album_dir(&self, album_dir: &Path) -> Result<(), JellyfinError> {
+        if album_dir.exists() {
+            fs::remove_dir_all(album_dir).map_err(|e| {
+                JellyfinError::GenericError(format!("Failed to delete album dir: {}", e))
+            })?;
+        }
+
+        // remove the artist directory if it's now empty
+        let parent_dir = album_dir.parent().ok_or_else(|| {
+            JellyfinError::GenericError("Failed to get parent directory".to_string())
+        })?;
+
+        if parent_dir.exists() && parent_dir.is_dir() {
+            let entries = fs::read_dir(parent_dir)
+                .map_err(|e| JellyfinError::GenericError(format!("Failed to read dir: {}", e)))?;
+
+            if entries.count() == 0 {
+                fs::remove_dir(parent_dir).map_err(|e| {
+                    JellyfinError::GenericError(format!("Failed to delete parent dir: {}", e))
+                })?;
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn search_albums_by_artist(
+        &self,
+        search: &str,
+        access_token: &str,
+        user_id: Option<&str>,
+    ) -> Result<JellyfinItemsResponse, JellyfinError> {
+        let artist_results = self
+            .jellyfin_client
+            .search_album_artists(search, access_token, user_id)
+            .await?;
+
+        let album_artist_ids: Vec<String> = artist_results
+            .items
+            .iter()
+            .map(|item| item.id.clone())
+            .collect();
+
+        if album_artist_ids.is_empty() {
+            return Ok(JellyfinItemsResponse {
+                total_record_count: 0,
+                start_index: 0,
+                items: Vec::new(),
+                stale: artist_results.stale,
+            });
+        }
+
+        let artist_album_results = self
+            .jellyfin_client
+            .search_albums_by_album_artist(album_artist_ids, None, None, access_token, user_id)
+            .await;
+
+        artist_album_results
+    }
+
+    fn add_downloaded_state(
+        &self,
+        res: &JellyfinItemsResponse,
+    ) -> Result<AlbumSearchResponse, JellyfinError> {
+        let album_ids = res
+            .items
+            .iter()
+            .map(|item| item.id.clone())
+            .collect::<Vec<_>>();
+
+        let downloaded_albums = self
+            .repository
+            .get_downloaded_album_ids(album_ids)
+            .map_err(|e| JellyfinError::GenericError(e.to_string()))?;
+
+        let items = res
+            .items
+            .clone()
+            .into_iter()
+            .map(|item| AlbumSearchResponseItem {
+                image_url: self.get_thumbnail_url(&item),
+                name: item.name,
+                id: item.id.clone(),
+                album_artist: item
+                    .album_artist
+                    .unwrap_or_else(|| "Unknown Artist".to_string()),
+                downloaded: downloaded_albums.contains(&item.id),
+                outdated: None,
+            })
+            .collect::<Vec<_>>();
+
+        Ok(AlbumSearchResponse {
+            total_record_count: res.total_record_count,
+            start_index: res.start_index,
+            items,
+            stale: res.stale,
+        })
+    }
+
+    // a thumbnail protocol url for the item's art, which is fetched into the
+    // image cache when the webview first asks for it. missing art isn't an
+    // error, there's just no image
+    fn get_thumbnail_url(&self, item: &JellyfinItem) -> Option<String> {
+        let tag = item.image_tags.as_ref()?.primary.as_deref()?;
+
+        // windows and android webviews only load custom protocols over http
+        let base = if cfg!(any(windows, target_os = "android")) {
+            "http://thumbnail.localhost"
+        } else {
+            "thumbnail://localhost"
+        };
+
+        Some(format!("{}/{}/{}", base, item.id, tag))
+    }
+
+    // the item's thumbnail from the image cache, fetching it if needed. None if
+    // the server has no such image
+    pub async fn get_thumbnail(
+        &self,
+        item_id: &str,
+        tag: &str,
+        access_token: &str,
+    ) -> Result<Option<PathBuf>, JellyfinError> {
+        let key = ImageCache::key(item_id, tag);
+
+        if let Some(path) = self.image_cache.get(&key) {
+            return Ok(Some(path));
+        }
+
+        let guard = self.image_cache.lock_fetch(&key).await;
+        let path = self.fetch_thumbnail(item_id, tag, &key, access_token).await;
+        self.image_cache.finish_fetch(&key, guard);
+
+        path
+    }
+
+    async fn fetch_thumbnail(
+        &self,
+        item_id: &str,
+        tag: &str,
+        key: &str,
+        access_token: &str,
+    ) -> Result<Option<PathBuf>, JellyfinError> {
+        // fetched by whoever held the key before us
+        if let Some(path) = self.image_cache.get(key) {
+            return Ok(Some(path));
+        }
+
+        self.image_cache.ensure_dir().map_err(|e| {
+            JellyfinError::GenericError(format!("Failed to create image cache dir: {}", e))
+        })?;
+
+        let path = self
+            .jellyfin_client
+            .download_thumbnail(
+                item_id,
+                tag,
+                THUMBNAIL_SIZE,
+                self.image_cache.dir(),
+                key,
+                access_token,
+            )
+            .await?;
+
+        if let Some(path) = &path {
+            if let Err(e) = self.image_cache.insert(key, path) {
+                eprintln!(
+                    "Failed to add thumbnail for {} to the cache: {}",
+                    item_id, e
+                );
+            }
+        }
+
+        Ok(path)
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub async fn download_track(
+        &self,
+        track_id: &str,
+        download_path: &str,
+        access_token: &str,
+        user_id: Option<&str>,
+        quality: &DownloadQuality,
+        on_progress: impl FnMut(u64, Option<u64>),
+        cancelled: &AtomicBool,
+        limiter: &BandwidthLimiter,
+    ) -> Result<FileDigest, JellyfinError> {
+        self.jellyfin_client
+            .download_track(
+                track_id,
+                download_path,
+                access_token,
+                user_id,
+                quality,
+                on_progress,
+                cancelled,
+                limiter,
+            )
+            .await
+    }
+
+    // removes the least recently played unpinned albums until `required` more
+    // bytes fit under the quota. albums that are downloading are never removed,
+    // and nothing is removed unless enough can be to make the room
+    pub async fn enforce_library_quota(
+        &self,
+        app_handle: &tauri::AppHandle,
+        quota: u64,
+        required: u64,
+    ) -> Result<(), JellyfinError> {
+        let used = self
+            .repository
+            .get_downloaded_size(None)
+            .map_err(|e| JellyfinError::GenericError(e.to_string()))?;
+
+        if used + required <= quota {
+            return Ok(());
+        }
+
+        let candidates = self
+            .repository
+            .get_eviction_candidates()
+            .map_err(|e| JellyfinError::GenericError(e.to_string()))?;
+
+        let mut freed = 0;
+        let mut to_evict = Vec::new();
+
+        for album in candidates {
+            if used.saturating_sub(freed) + required <= quota {
+                break;
+            }
+
+            if self.download_queue.is_active(&album.jellyfin_id) {
+                continue;
+            }
+
+            let bytes = self
+                .repository
+                .get_downloaded_size(Some(album.id))
+                .map_err(|e| JellyfinError::GenericError(e.to_string()))?;
+
+            freed += bytes;
+            to_evict.push((album, bytes));
+        }
+
+        let needed = used.saturating_sub(freed) + required;
+
+        if needed > quota {
+            return Err(JellyfinError::InsufficientSpace {
+                required: needed,
+                available: quota,
+            });
+        }
+
+        let mut evicted = Vec::new();
+
+        for (album, bytes) in to_evict {
+            self.delete_album(&album.jellyfin_id).await?;
+
+            evicted.push(EvictedAlbum {
+                album_id: album.jellyfin_id,
+                title: album.title,
+                artist: album.artist,
+                bytes,
+            });
+        }
+
+        if !evicted.is_empty() {
+            app_handle
+                .emit("albums-evicted", AlbumsEvicted { albums: evicted })
+                .unwrap();
+        }
+
+        Ok(())
+    }
+
+    // checks an album will fit before it downloads, rather than running out of
+    // space halfway through. the quota is made room in first
+    pub async fn ensure_space_for_album(
+        &self,
+        app_handle: &tauri::AppHandle,
+        album_dir: &Path,
+        required: u64,
+        quota: Option<u64>,
+    ) -> Result<(), JellyfinError> {
+        if let Some(quota) = quota {
+            self.enforce_library_quota(app_handle, quota, required)
+                .await?;
+        }
+
+        let available = fs2::available_space(album_dir).map_err(|e| {
+            JellyfinError::GenericError(format!("Failed to check free space: {}", e))
+        })?;
+
+        if available < required {
+            return Err(JellyfinError::InsufficientSpace {
+                required,
+                available,
+            });
+        }
+
+        Ok(())
+    }
+
+    pub fn mark_album_played(&self, album_id: &str) -> Result<(), JellyfinError> {
+        self.repository
+            .mark_album_played(album_id)
+            .map_err(|e| JellyfinError::GenericError(e.to_string()))
+    }
+
+    pub fn set_album_pinned(&self, album_id: &str, pinned: bool) -> Result<(), JellyfinError> {
+        self.repository
+            .set_album_pinned(album_id, pinned)
+            .map_err(|e| JellyfinError::GenericError(e.to_string()))
+    }
+
+    // re-checks every downloaded track against the size and hash saved when it
+    // downloaded. tracks from before hashes were saved get theirs saved now
+    pub fn verify_library(&self) -> Result<LibraryVerification, JellyfinError> {
+        let albums = self
+            .repository
+            .get_downloaded_albums()
+            .map_err(|e| JellyfinError::GenericError(e.to_string()))?;
+
+        let mut checked_tracks = 0;
+        let mut damaged_tracks = Vec::new();
+
+        for album in albums {
+            let tracks = self
+                .repository
+                .get_album_details(&album.jellyfin_id)
+                .map_err(|e| JellyfinError::GenericError(e.to_string()))?
+                .map(|(_, tracks)| tracks)
+                .unwrap_or_default();
+
+            // tracks left out of a partial download have nothing to check
+            for track in tracks.into_iter().filter(|track| track.path.is_some()) {
+                checked_tracks += 1;
+
+                let check = check_track(&track).map_err(|e| {
+                    JellyfinError::GenericError(format!("Failed to check track: {}", e))
+                })?;
+
+                match check {
+                    TrackCheck::Intact(digest) if track.sha256.is_none() => self
+                        .repository
+                        .set_track_digest(track.id, digest.size as i64, &digest.sha256)
+                        .map_err(|e| JellyfinError::GenericError(e.to_string()))?,
+                    TrackCheck::Intact(_) => {}
+                    TrackCheck::Damaged(issue) => damaged_tracks.push(DamagedTrack {
+                        album_id: album.jellyfin_id.clone(),
+                        album_title: album.title.clone(),
+                        track_id: track.jellyfin_id,
+                        track_name: track.name,
+                        path: track.path,
+                        issue,
+                    }),
+                }
+            }
+        }
+
+        Ok(LibraryVerification {
+            checked_tracks,
+            damaged_tracks,
+        })
+    }
+
+    // queues the tracks to download again at the quality their album was
+    // downloaded in. the damaged files stay until the new copies are in, so a
+    // failed repair loses nothing
+    pub async fn repair_tracks(
+        &self,
+        app_handle: &AppHandle,
+        track_ids: &[String],
+        access_token: &str,
+        user_id: &str,
+    ) -> Result<(), JellyfinError> {
+        let mut by_album: HashMap<String, (DownloadQuality, Vec<String>)> = HashMap::new();
+
+        for track_id in track_ids {
+            let (track, album) = self
+                .repository
+                .find_track_with_album(track_id)
+                .map_err(|e| JellyfinError::GenericError(e.to_string()))?
+                .ok_or_else(|| JellyfinError::ApiError {
+                    status: StatusCode::NOT_FOUND,
+                    message: "Track not found".to_string(),
+                })?;
+
+            if track.path.is_none() {
+                return Err(JellyfinError::GenericError(
+                    "Track has not been downloaded".to_string(),
+                ));
+            }
+
+            by_album
+                .entry(album.jellyfin_id)
+                .or_insert_with(|| (album.quality.parse().unwrap_or_default(), Vec::new()))
+                .1
+                .push(track.jellyfin_id);
+        }
+
+        for (album_id, (quality, track_ids)) in by_album {
+            self.download_album(
+                app_handle,
+                &album_id,
+                Some(track_ids),
+                access_token,
+                user_id,
+                quality,
+            )
+            .await?;
+        }
+
+        Ok(())
+    }
+
+    // saves the cover, plus any back cover, disc art and album artist image the
+    // server has, at full resolution into the album directory
+    pub async fn download_album_artwork(
+        &self,
+        album_id: &str,
+        dir: &Path,
+        access_token: &str,
+    ) -> Result<AlbumArtwork, JellyfinError> {
+        let album = self
+            .jellyfin_client
+            .get_jellyfin_item(album_id, access_token, None, true)
+            .await?;
+
+        let tags = album.image_tags.as_ref();
+
+        let image_path = match tags.and_then(|tags| tags.primary.as_deref()) {
+            Some(tag) => {
+                self.jellyfin_client
+                    .download_image(album_id, "Primary", Some(tag), dir, "cover", access_token)
+                    .await?
+            }
+            None => None,
+        };
+
+        // the extras are nice to have, so a failure shouldn't stop the download
+        let back_image_path = match tags.and_then(|tags| tags.box_rear.as_deref()) {
+            Some(tag) => {
+                self.download_optional_image(
+                    album_id,
+                    "BoxRear",
+                    Some(tag),
+                    dir,
+                    "back",
+                    access_token,
+                )
+                .await
+            }
+            None => None,
+        };
+
+        let disc_image_path = match tags.and_then(|tags| tags.disc.as_deref()) {
+            Some(tag) => {
+                self.download_optional_image(album_id, "Disc", Some(tag), dir, "disc", access_token)
+                    .await
+            }
+            None => None,
+        };
+
+        let artist_image_path = match album.album_artists.as_ref().and_then(|a| a.first()) {
+            Some(artist) => {
+                self.download_optional_image(
+                    &artist.id,
+                    "Primary",
+                    None,
+                    dir,
+                    "artist",
+                    access_token,
+                )
+                .await
+            }
+            None => None,
+        };
+
+        Ok(AlbumArtwork {
+            image_path: image_path.map(|p| p.to_string_lossy().to_string()),
+            back_image_path: back_image_path.map(|p| p.to_string_lossy().to_string()),
+            disc_image_path: disc_image_path.map(|p| p.to_string_lossy().to_string()),
+            artist_image_path: artist_image_path.map(|p| p.to_string_lossy().to_string()),
+        })
+    }
+
+    async fn download_optional_image(
+        &self,
+        item_id: &str,
+        image_type: &str,
+        image_tag: Option<&str>,
+        dir: &Path,
+        file_stem: &str,
+        access_token: &str,
+    ) -> Option<PathBuf> {
+        self.jellyfin_client
+            .download_image(item_id, image_type, image_tag, dir, file_stem, access_token)
+            .await
+            .unwrap_or_else(|e| {
+                eprintln!(
+                    "Failed to download {} image for {}: {}",
+                    image_type, item_id, e
+                );
+                None
+            })
+    }
+
+    pub async fn get_tracks(
+        &self,
+        album_id: &str,
+        access_token: &str,
+        user_id: Option<&str>,
+        revalidate: bool,
+    ) -> Result<JellyfinItemsResponse, JellyfinError> {
+        self.jellyfin_client
+            .get_tracks(album_id, access_token, user_id, revalidate)
+            .await
+    }
+
+    pub async fn get_album_info(&self, album_id: &str) -> Result<AlbumInfoResponse, JellyfinError> {
+        let (local_album, local_tracks) = self
+            .repository
+            .get_album_details(album_id)
+            .map_err(|e| JellyfinError::GenericError(e.to_string()))?
+            .ok_or_else(|| JellyfinError::ApiError {
+                status: StatusCode::NOT_FOUND,
+                message: "Album not found".to_string(),
+            })?;
+
+        let tracks = local_tracks
+            .into_iter()
+            .map(|track| AlbumTrackResponse {
+                id: track.jellyfin_id,
+                name: track.name,
+                available: track.path.is_some(),
+                playback_url: track.path.unwrap_or_default(),
+                disc_number: track.disc_number.map(|disc| disc as u32),
+                artists: from_json_list(track.artists),
+                duration_seconds: track.run_time_ticks.map(ticks_to_seconds),
+                is_favorite: track.is_favorite,
+                play_count: track.play_count as u32,
+            })
+            .collect::<Vec<_>>();
+
+        let result = AlbumInfoResponse {
+            id: local_album.jellyfin_id,
+            name: local_album.title,
+            artist: local_album.artist,
+            discs: group_discs(&tracks),
+            tracks,
+            image_url: local_album.image_path,
+            back_image_url: local_album.back_image_path,
+            disc_image_url: local_album.disc_image_path,
+            artist_image_url: local_album.artist_image_path,
+            quality: local_album.quality,
+            production_year: local_album.production_year,
+            genres: from_json_list(local_album.genres),
+            artists: from_json_list(local_album.artists),
+            duration_seconds: local_album.run_time_ticks.map(ticks_to_seconds),
+            overview: local_album.overview,
+            date_created: local_album.date_created,
+            is_favorite: local_album.is_favorite,
+            play_count: local_album.play_count as u32,
+        };
+
+        Ok(result)
+    }
+
+    pub async fn get_item(
+        &self,
+        item_id: &str,
+        access_token: &str,
+    ) -> Result<JellyfinItem, JellyfinError> {
+        self.jellyfin_client
+            .get_jellyfin_item(item_id, access_token, None, false)
+            .await
+    }
+
+    // tells the server this session can be remote controlled
+    pub async fn report_capabilities(&self, access_token: &str) -> Result<(), JellyfinError> {
+        let capabilities = ClientCapabilities {
+            playable_media_types: vec!["Audio".to_string()],
+            supported_commands: vec!["SetVolume".to_string()],
+            supports_media_control: true,
+        };
+
+        self.jellyfin_client
+            .report_capabilities(&capabilities, access_token)
+            .await
+    }
+
+    pub fn get_websocket_url(&self, access_token: &str) -> Result<Url, JellyfinError> {
+        self.jellyfin_client.get_websocket_url(access_token)
+    }
+
+    // flags downloaded albums touched by a LibraryChanged notification, returning
+    // the albums that were newly flagged along with why
+    pub async fn handle_library_changes(
+        &self,
+        update: &LibraryUpdateInfo,
+        access_token: &str,
+    ) -> Result<Vec<(String, String)>, JellyfinError> {
+        // anything we cached may be out of date now
+        self.jellyfin_client.expire_cached_responses()?;
+
+        let mut flagged = Vec::new();
+
+        let removed = self
+            .repository
+            .find_downloaded_albums_containing(&update.items_removed)
+            .map_err(|e| JellyfinError::GenericError(e.to_string()))?;
+
+        for album in removed {
+            // a removed track just means the album changed
+            let reason = if update.items_removed.contains(&album.jellyfin_id) {
+                OUTDATED_REMOVED
+            } else {
+                OUTDATED_UPDATED
+            };
+
+            self.flag_outdated_album(&album, reason, &mut flagged)?;
+        }
+
+        let updated = self
+            .repository
+            .find_downloaded_albums_containing(&update.items_updated)
+            .map_err(|e| JellyfinError::GenericError(e.to_string()))?;
+
+        for album in updated {
+            self.flag_outdated_album(&album, OUTDATED_UPDATED, &mut flagged)?;
+        }
+
+        // added tracks aren't in the db yet, so look up which album they belong to
+        if !update.items_added.is_empty() {
+            let added = self
+                .jellyfin_client
+                .get_jellyfin_items(&update.items_added, access_token, None, true)
+                .await?;
+
+            let album_ids = added
+                .items
+                .into_iter()
+                .filter_map(|item| item.album_id)
+                .collect::<Vec<_>>();
+
+            let albums = self
+                .repository
+                .find_downloaded_albums_containing(&album_ids)
+                .map_err(|e| JellyfinError::GenericError(e.to_string()))?;
+
+            for album in albums {
+                self.flag_outdated_album(&album, OUTDATED_UPDATED, &mut flagged)?;
+            }
+        }
+
+        Ok(flagged)
+    }
+
+    // catches up on changes made while we weren't listening to the websocket
+    pub async fn check_for_library_changes(
+        &self,
+        access_token: &str,
+    ) -> Result<Vec<(String, String)>, JellyfinError> {
+        // we don't know what changed while we were away
+        self.jellyfin_client.expire_cached_responses()?;
+
+        let downloaded = self
+            .repository
+            .get_downloaded_albums()
+            .map_err(|e| JellyfinError::GenericError(e.to_string()))?;
+
+        if downloaded.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let album_ids = downloaded
+            .iter()
+            .map(|album| album.jellyfin_id.clone())
+            .collect::<Vec<_>>();
+
+        // albums missing from the server were deleted
+        let on_server = self
+            .jellyfin_client
+            .get_jellyfin_items(&album_ids, access_token, None, true)
+            .await?
+            .items
+            .into_iter()
+            .map(|item| item.id)
+            .collect::<HashSet<_>>();
+
+        let removed_ids = album_ids
+            .iter()
+            .filter(|id| !on_server.contains(*id))
+            .cloned()
+            .collect::<Vec<_>>();
+
+        // anything saved since the oldest sync might belong to one of our albums,
+        // but it only counts if it's newer than that album's own sync
+        let updated_ids = match downloaded.iter().filter_map(|a| a.synced_at).min() {
+            Some(since) => {
+                let synced_at = downloaded
+                    .iter()
+                    .map(|album| (album.jellyfin_id.as_str(), album.synced_at))
+                    .collect::<HashMap<_, _>>();
+
+                self.jellyfin_client
+                    .get_items_changed_since(&since, access_token)
+                    .await?
+                    .items
+                    .into_iter()
+                    .filter_map(|item| {
+                        // a changed track means its album changed
+                        let album_id = match item.item_type.as_deref() {
+                            Some("MusicAlbum") => item.id,
+                            _ => item.album_id?,
+                        };
+
+                        let changed =
+                            match (synced_at.get(album_id.as_str())?, item.date_last_saved) {
+                                (Some(synced), Some(saved)) => saved.naive_utc() > *synced,
+                                _ => true,
+                            };
+
+                        changed.then_some(album_id)
+                    })
+                    .collect::<Vec<_>>()
+            }
+            None => Vec::new(),
+        };
+
+        let flagged = self
+            .handle_library_changes(
+                &LibraryUpdateInfo {
+                    items_added: Vec::new(),
+                    items_updated: updated_ids,
+                    items_removed: removed_ids,
+                },
+                access_token,
+            )
+            .await?;
+
+        self.repository
+            .mark_albums_as_synced(&album_ids)
+            .map_err(|e| JellyfinError::GenericError(e.to_string()))?;
+
+        Ok(flagged)
+    }
+
+    fn flag_outdated_album(
+        &self,
+        album: &Album,
+        reason: &str,
+        flagged: &mut Vec<(String, String)>,
+    ) -> Result<(), JellyfinError> {
+        // removed wins over updated, and there's no need to flag twice
+        if album.outdated.as_deref() == Some(reason)
+            || album.outdated.as_deref() == Some(OUTDATED_REMOVED)
+            || flagged.iter().any(|(id, _)| id == &album.jellyfin_id)
+        {
+            return Ok(());
+        }
+
+        self.repository
+            .mark_album_as_outdated(&album.jellyfin_id, reason)
+            .map_err(|e| JellyfinError::GenericError(e.to_string()))?;
+
+        flagged.push((album.jellyfin_id.clone(), reason.to_string()));
+
+        Ok(())
+    }
+
+    // brings a downloaded album in line with the server. removed tracks are
+    // deleted, added ones and any the server saved since are queued, and the
+    // album's details, artwork and directory follow the server's
+    pub async fn refresh_album(
+        &self,
+        app_handle: &AppHandle,
+        album_id: &str,
+        access_token: &str,
+        user_id: &str,
+    ) -> Result<AlbumRefresh, JellyfinError> {
+        let (album, local_tracks) = self
+            .repository
+            .get_album_details(album_id)
+            .map_err(|e| JellyfinError::GenericError(e.to_string()))?
+            .ok_or_else(|| JellyfinError::ApiError {
+                status: StatusCode::NOT_FOUND,
+                message: "Album not found".to_string(),
+            })?;
+
+        let album_path = album
+            .path
+            .clone()
+            .ok_or_else(|| JellyfinError::GenericError("Album is not downloaded".to_string()))?;
+
+        if self.download_queue.is_active(album_id) {
+            return Err(JellyfinError::GenericError(
+                "Album is already downloading".to_string(),
+            ));
+        }
+
+        let album_info = self
+            .jellyfin_client
+            .get_jellyfin_item(album_id, access_token, Some(user_id), true)
+            .await?;
+
+        let tracks = self
+            .jellyfin_client
+            .get_tracks(album_id, access_token, Some(user_id), true)
+            .await?;
+
+        if tracks.stale {
+            return Err(JellyfinError::GenericError(
+                "Could not reach the server".to_string(),
+            ));
+        }
+
+        let mut refresh = AlbumRefresh {
+            album_id: album_id.to_string(),
+            ..Default::default()
+        };
+
+        let artist = album_info
+            .album_artist
+            .clone()
+            .unwrap_or_else(|| "Unknown Artist".to_string());
+
+        if album_info.name != album.title {
+            refresh.previous_title = Some(album.title.clone());
+        }
+
+        if artist != album.artist {
+            refresh.previous_artist = Some(album.artist.clone());
+        }
+
+        // everything that needs the server happens before anything on disk
+        // changes. new artwork goes into a temporary dir inside the album's
+        let old_dir = PathBuf::from(&album_path);
+        let dir = self.album_dir(app_handle, &artist, &album_info.name)?;
+        let artwork_dir = old_dir.join(".refresh");
+
+        let image_id = album_info
+            .image_tags
+            .as_ref()
+            .and_then(|tags| tags.primary.as_deref());
+
+        let new_artwork = if image_id != album.image_id.as_deref() {
+            refresh.artwork_changed = true;
+
+            fs::create_dir_all(&artwork_dir).map_err(|e| {
+                JellyfinError::GenericError(format!("Failed to create artwork directory: {}", e))
+            })?;
+
+            match self
+                .download_album_artwork(album_id, &artwork_dir, access_token)
+                .await
+            {
+                Ok(artwork) => Some(artwork),
+                Err(e) => {
+                    let _ = fs::remove_dir_all(&artwork_dir);
+                    return Err(e);
+                }
+            }
+        } else {
+            None
+        };
+
+        // the directory is named after the album, so it moves when that changes
+        if dir != old_dir {
+            let moved = dir
+                .parent()
+                .map_or(Ok(()), fs::create_dir_all)
+                .and_then(|_| fs::rename(&old_dir, &dir));
+
+            if let Err(e) = moved {
+                let _ = fs::remove_dir_all(&artwork_dir);
+
+                return Err(JellyfinError::GenericError(format!(
+                    "Failed to move album directory: {}",
+                    e
+                )));
+            }
+        }
+
+        let relocate = |path: Option<String>| {
+            path.and_then(|path| {
+                Path::new(&path)
+                    .file_name()
+                    .map(|name| dir.join(name).to_string_lossy().to_string())
+            })
+        };
+
+        let old_artwork = [
+            &album.image_path,
+            &album.back_image_path,
+            &album.disc_image_path,
+            &album.artist_image_path,
+        ]
+        .into_iter()
+        .filter_map(|path| relocate(path.clone()))
+        .collect::<Vec<_>>();
+
+        // the new artwork stays in the temporary dir until the refresh is saved,
+        // since it may replace old files of the same name
+        let moved_artwork_dir = dir.join(".refresh");
+
+        let artwork = match &new_artwork {
+            Some(artwork) => AlbumArtwork {
+                image_path: relocate(artwork.image_path.clone()),
+                back_image_path: relocate(artwork.back_image_path.clone()),
+                disc_image_path: relocate(artwork.disc_image_path.clone()),
+                artist_image_path: relocate(artwork.artist_image_path.clone()),
+            },
+            None => AlbumArtwork {
+                image_path: relocate(album.image_path.clone()),
+                back_image_path: relocate(album.back_image_path.clone()),
+                disc_image_path: relocate(album.disc_image_path.clone()),
+                artist_image_path: relocate(album.artist_image_path.clone()),
+            },
+        };
+
+        let server_ids = tracks
+            .items
+            .iter()
+            .map(|track| track.id.as_str())
+            .collect::<HashSet<_>>();
+
+        // tracks left out of a partial download stay left out, so new ones are
+        // only fetched for albums that were downloaded whole
+        let whole_album = local_tracks.iter().all(|track| track.path.is_some());
+
+        let mut local_tracks = local_tracks
+            .into_iter()
+            .map(|track| (track.jellyfin_id.clone(), track))
+            .collect::<HashMap<_, _>>();
+
+        // files of removed tracks, deleted once the refresh is saved
+        let mut stale_files = Vec::new();
+
+        for track in local_tracks.values() {
+            if server_ids.contains(track.jellyfin_id.as_str()) {
+                continue;
+            }
+
+            stale_files.extend(relocate(track.path.clone()));
+            refresh.removed.push(track.name.clone());
+        }
+
+        let mut to_download = Vec::new();
+        let mut new_tracks = Vec::new();
+
+        for track in &tracks.items {
+            let date_modified = track.date_last_saved.map(|date| date.naive_utc());
+
+            let (path, size, sha256, date_modified) = match local_tracks.remove(&track.id) {
+                Some(local) => {
+                    if local.name != track.name {
+                        refresh.renamed.push(TrackRename {
+                            track_id: track.id.clone(),
+                            from: local.name.clone(),
+                            to: track.name.clone(),
+                        });
+                    }
+
+                    let path = relocate(local.path);
+
+                    // tracks from before modified dates were kept just pick one up
+                    let replaced = path.is_some()
+                        && local
+                            .date_modified
+                            .zip(date_modified)
+                            .is_some_and(|(local, server)| server > local);
+
+                    // a replaced track keeps playing from its old file until
+                    // the new one lands over it. the old modified date is what
+                    // tells the queue its copy is out of date
+                    if replaced {
+                        refresh.replaced.push(track.name.clone());
+                        to_download.push(track.id.clone());
+                        (path, local.size, local.sha256, local.date_modified)
+                    } else {
+                        (path, local.size, local.sha256, date_modified)
+                    }
+                }
+                None => {
+                    refresh.added.push(track.name.clone());
+
+                    if whole_album {
+                        to_download.push(track.id.clone());
+                    }
+
+                    (None, None, None, date_modified)
+                }
+            };
+
+            new_tracks.push(NewTrack {
+                jellyfin_id: &track.id,
+                name: &track.name,
+                album_id: album.id,
+                path,
+                track_index: track.index_number.unwrap_or(0) as i32,
+                disc_number: track.parent_index_number.map(|d| d as i32),
+                artists: to_json_list(track.artist_names()),
+                run_time_ticks: track.run_time_ticks,
+                is_favorite: track.is_favorite(),
+                play_count: track.play_count() as i32,
+                size,
+                sha256,
+                date_modified,
+            });
+        }
+
+        let saved = self.repository.refresh_album(
+            album_id,
+            &AlbumMetadata {
+                title: &album_info.name,
+                artist: &artist,
+                image_id,
+                production_year: album_info.production_year,
+                genres: to_json_list(album_info.genres.clone().unwrap_or_default()),
+                artists: to_json_list(album_info.artist_names()),
+                run_time_ticks: album_info.run_time_ticks,
+                overview: album_info.overview.as_deref(),
+                is_favorite: album_info.is_favorite(),
+                play_count: album_info.play_count() as i32,
+            },
+            &dir.to_string_lossy(),
+            &artwork,
+            &new_tracks,
+        );
+
+        // the library still points at the old directory and artwork, so the new
+        // artwork is dropped and the directory moves back
+        if let Err(e) = saved {
+            let _ = fs::remove_dir_all(&moved_artwork_dir);
+
+            if dir != old_dir {
+                if let Err(e) = fs::rename(&dir, &old_dir) {
+                    eprintln!("Failed to move album directory back: {}", e);
+                }
+            }
+
+            return Err(JellyfinError::GenericError(e.to_string()));
+        }
+
+        if new_artwork.is_some() {
+            for path in artwork.paths() {
+                if let Some(name) = Path::new(path).file_name() {
+                    if let Err(e) = fs::rename(moved_artwork_dir.join(name), path) {
+                        eprintln!("Failed to move artwork into {}: {}", path, e);
+                    }
+                }
+            }
+
+            let _ = fs::remove_dir_all(&moved_artwork_dir);
+        }
+
+        let artwork_paths = artwork.paths().collect::<Vec<_>>();
+
+        for path in stale_files.iter().chain(
+            old_artwork
+                .iter()
+                .filter(|path| !artwork_paths.contains(&path.as_str())),
+        ) {
+            if let Err(e) = fs::remove_file(path) {
+                eprintln!("Failed to remove {}: {}", path, e);
+            }
+        }
+
+        if !to_download.is_empty() {
+            refresh.queued = to_download.len();
+
+            self.download_album(
+                app_handle,
+                album_id,
+                Some(to_download),
+                access_token,
+                user_id,
+                album.quality.parse().unwrap_or_default(),
+            )
+            .await?;
+        }
+
+        Ok(refresh)
+    }
+
+    // refreshes every downloaded album the server has changed, returning the
+    // ones that turned out to be different
+    pub async fn refresh_outdated_albums(
+        &self,
+        app_handle: &AppHandle,
+        access_token: &str,
+        user_id: &str,
+    ) -> Result<Vec<AlbumRefresh>, JellyfinError> {
+        self.check_for_library_changes(access_token).await?;
+
+        let outdated = self
+            .repository
+            .get_downloaded_albums()
+            .map_err(|e| JellyfinError::GenericError(e.to_string()))?
+            .into_iter()
+            .filter(|album| album.outdated.as_deref() == Some(OUTDATED_UPDATED));
+
+        let mut refreshed = Vec::new();
+
+        for album in outdated {
+            // one album failing shouldn't hold up the rest
+            match self
+                .refresh_album(app_handle, &album.jellyfin_id, access_token, user_id)
+                .await
+            {
+                Ok(refresh) if refresh.has_changes() => refreshed.push(refresh),
+                Ok(_) => {}
+                Err(e) => eprintln!("Error refreshing album {}: {}", album.jellyfin_id, e),
+            }
+        }
+
+        Ok(refreshed)
+    }
+
+    pub async fn is_album_downloaded(&self, album_id: &str) -> Result<bool, JellyfinError> {
+        let album = self
+            .repository
+            .find_album(album_id)
+            .map_err(|e| JellyfinError::GenericError(e.to_string()))?;
+
+        Ok(album.is_some_and(|album| album.path.is_some()))
+    }
+
+    // builds album info from the server, with tracks that stream instead of
+    // playing from disk
+    pub async fn get_album_info_online(
+        &self,
+        album_id: &str,
+        access_token: &str,
+        user_id: Option<&str>,
+    ) -> Result<AlbumInfoResponse, JellyfinError> {
+        let album = self
+            .jellyfin_client
+            .get_jellyfin_item(album_id, access_token, user_id, false)
+            .await?;
+
+        let tracks = self
+            .jellyfin_client
+            .get_tracks(album_id, access_token, user_id, false)
+            .await?;
+
+        let tracks = tracks
+            .items
+            .into_iter()
+            .map(|track| {
+                Ok(AlbumTrackResponse {
+                    playback_url: self.jellyfin_client.get_stream_url(
+                        &track.id,
+                        access_token,
+                        user_id,
+                    )?,
+                    disc_number: track.parent_index_number,
+                    artists: track.artist_names(),
+                    duration_seconds: track.run_time_ticks.map(ticks_to_seconds),
+                    is_favorite: track.is_favorite(),
+                    play_count: track.play_count(),
+                    available: true,
+                    id: track.id,
+                    name: track.name,
+                })
+            })
+            .collect::<Result<Vec<_>, JellyfinError>>()?;
+
+        let image_url = self.get_thumbnail_url(&album);
+
+        Ok(AlbumInfoResponse {
+            discs: group_discs(&tracks),
+            artists: album.artist_names(),
+            is_favorite: album.is_favorite(),
+            play_count: album.play_count(),
+            id: album.id,
+            name: album.name,
+            artist: album
+                .album_artist
+                .unwrap_or_else(|| "Unknown Artist".to_string()),
+            tracks,
+            image_url,
+            back_image_url: None,
+            disc_image_url: None,
+            artist_image_url: None,
+            quality: "stream".to_string(),
+            production_year: album.production_year,
+            genres: album.genres.unwrap_or_default(),
+            duration_seconds: album.run_time_ticks.map(ticks_to_seconds),
+            overview: album.overview,
+            date_created: album.date_created.map(|date| date.naive_utc()),
+        })
+    }
+
+    pub async fn sync_album(
+        &self,
+        album_id: &str,
+        access_token: &str,
+        user_id: Option<&str>,
+    ) -> Result<Album, JellyfinError> {
+        // Check if the album already exists in the database
+        if let Some(album) = self
+            .repository
+            .find_album(album_id)
+            .map_err(|e| JellyfinError::GenericError(e.to_string()))?
+        {
+            return Ok(album);
+        }
+
+        // album does not exist, we will insert it
+        let album_info = self
+            .jellyfin_client
+            .get_jellyfin_item(album_id, access_token, user_id, true)
+            .await?;
+
+        let image_id = album_info
+            .image_tags
+            .as_ref()
+            .and_then(|tags| tags.primary.as_deref());
+
+        let artist = album_info
+            .album_artist
+            .clone()
+            .unwrap_or_else(|| "Unknown Artist".to_string());
+
+        self.repository
+            .create_album(&NewAlbum {
+                jellyfin_id: album_id,
+                title: &album_info.name,
+                artist: &artist,
+                image_id,
+                created_at: Utc::now().naive_utc(),
+                updated_at: Utc::now().naive_utc(),
+                production_year: album_info.production_year,
+                genres: to_json_list(album_info.genres.clone().unwrap_or_default()),
+                artists: to_json_list(album_info.artist_names()),
+                run_time_ticks: album_info.run_time_ticks,
+                overview: album_info.overview.as_deref(),
+                date_created: album_info.date_created.map(|date| date.naive_utc()),
+                is_favorite: album_info.is_favorite(),
+                play_count: album_info.play_count() as i32,
+            })
+            .map_err(|e| JellyfinError::GenericError(e.to_string()))
+    }
+
+    pub fn album_dir(
+        &self,
+        app_handle: &AppHandle,
+        album_artist: &str,
+        album_name: &str,
+    ) -> Result<PathBuf, JellyfinError> {
+        let mut app_data_path = app_handle.path().app_data_dir().map_err(|e| {
+            JellyfinError::GenericError(format!("Failed to get app data dir: {}", e))
+        })?;
+
+        app_data_path.push("downloads");
+        app_data_path.push(sanitize(album_artist));
+        app_data_path.push(sanitize(album_name));
+
+        Ok(app_data_path)
+    }
+
+    pub fn create_album_dir(
+        &self,
+        app_handle: &AppHandle,
+        album_artist: &str,
+        album_name: &str,
+    ) -> Result<PathBuf, JellyfinError> {
+        let app_data_path = self.album_dir(app_handle, album_artist, album_name)?;
+
+        if !app_data_path.exists() {
+            fs::create_dir_all(&app_data_path).map_err(|e| {
+                JellyfinError::GenericError(format!("Failed to create album directory: {}", e))
+            })?;
+        }
+
+        Ok(app_data_path)
+    }
+
+    pub fn generate_track_name(
+        &self,
+        track: &JellyfinItem,
+        total_tracks: usize,
+        multi_disc: bool,
+        quality: &DownloadQuality,
+    ) -> String {
+        // transcoded files get the extension of the target codec
+        let extension = match quality.extension().or(track.container.as_deref()) {
+            Some(ext) => format!(".{}", ext),
+            None => "".to_string(),
+        };
+
+        let width = if total_tracks == 0 {
+            2
+        } else {
+            total_tracks.to_string().len()
+        };
+
+        let mut track_number =
+            format!("{:0width$}", track.index_number.unwrap_or(0), width = width);
+
+        // prefix the disc so tracks from different discs don't collide, e.g. 2-01
+        if multi_disc {
+            track_number = format!(
+                "{}-{}",
+                track.parent_index_number.unwrap_or(1),
+                track_number
+            );
+        }
+
+        format!("{} - {}{}", track_number, sanitize(&track.name), extension)
+    }
+
+    fn combine_jellyfin_items(
+        &self,
+        list1: Vec<JellyfinItem>,
+        list2: Vec<JellyfinItem>,
+    ) -> Vec<JellyfinItem> {
+        let mut unique_items: HashSet<JellyfinItem> = HashSet::new();
+
+        for item in list1 {
+            unique_items.insert(item);
+        }
+
+        for item in list2 {
             unique_items.insert(item);
         }
 
@@ -431,6 +2201,7 @@ impl MusicManager {
                 album_artist: album.artist,
                 downloaded: album.path.is_some(),
                 image_url: album.image_path,
+                outdated: album.outdated,
             })
             .collect::<Vec<_>>();
 
@@ -438,6 +2209,7 @@ impl MusicManager {
             total_record_count: items.len() as u32,
             start_index: offset.unwrap_or(0),
             items,
+            stale: false,
         })
     }
 
@@ -455,6 +2227,7 @@ impl MusicManager {
                 album_artist: album.artist,
                 downloaded: album.path.is_some(),
                 image_url: album.image_path,
+                outdated: album.outdated,
             })
             .collect::<Vec<_>>();
 
@@ -464,6 +2237,62 @@ impl MusicManager {
             total_record_count: total,
             start_index: 0,
             items,
+            stale: false,
         })
     }
 }
+
+// lists like genres and artists are stored as json arrays
+pub fn to_json_list(items: Vec<String>) -> Option<String> {
+    if items.is_empty() {
+        return None;
+    }
+
+    serde_json::to_string(&items).ok()
+}
+
+pub fn from_json_list(value: Option<String>) -> Vec<String> {
+    value
+        .and_then(|v| serde_json::from_str(&v).ok())
+        .unwrap_or_default()
+}
+
+// the union of two track selections, where None means the whole album
+fn merge_track_selection(
+    selection: Option<Vec<String>>,
+    other: Option<Vec<String>>,
+) -> Option<Vec<String>> {
+    let mut track_ids = selection?;
+
+    for track_id in other? {
+        if !track_ids.contains(&track_id) {
+            track_ids.push(track_id);
+        }
+    }
+
+    Some(track_ids)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ids(ids: &[&str]) -> Option<Vec<String>> {
+        Some(ids.iter().map(|id| id.to_string()).collect())
+    }
+
+    #[test]
+    fn merging_adds_tracks_not_already_selected() {
+        assert_eq!(
+            merge_track_selection(ids(&["a", "b"]), ids(&["b", "c"])),
+            ids(&["a", "b", "c"])
+        );
+    }
+
+    #[test]
+    fn merging_with_the_whole_album_is_the_whole_album() {
+        assert_eq!(merge_track_selection(None, ids(&["a"])), None);
+        assert_eq!(merge_track_selection(ids(&["a"]), None), None);
+        assert_eq!(merge_track_selection(None, None), None);
+    }
+}
@@ -0,0 +1,63 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+struct Bucket {
+    available: f64,
+    last_refill: Instant,
+}
+
+// A token bucket shared by every track being downloaded, so the cap applies to
+// the total rate rather than to each stream. The limit can be changed while
+// downloads are running.
+pub struct BandwidthLimiter {
+    // bytes per second, zero for no limit
+    limit: AtomicU64,
+    bucket: Mutex<Bucket>,
+}
+
+impl BandwidthLimiter {
+    pub fn new(limit: Option<u64>) -> Self {
+        Self {
+            limit: AtomicU64::new(limit.unwrap_or(0)),
+            bucket: Mutex::new(Bucket {
+                available: 0.0,
+                last_refill: Instant::now(),
+            }),
+        }
+    }
+
+    pub fn set_limit(&self, limit: Option<u64>) {
+        self.limit.store(limit.unwrap_or(0), Ordering::Relaxed);
+    }
+
+    // waits until the bytes just received fit under the limit
+    pub async fn acquire(&self, bytes: u64) {
+        let limit = self.limit.load(Ordering::Relaxed);
+
+        if limit == 0 {
+            return;
+        }
+
+        let wait = {
+            let mut bucket = self.bucket.lock().unwrap();
+            let now = Instant::now();
+            let refill = now.duration_since(bucket.last_refill).as_secs_f64() * limit as f64;
+
+            // at most a second's worth builds up, so idle time doesn't become a burst
+            bucket.available = (bucket.available + refill).min(limit as f64);
+            bucket.last_refill = now;
+            bucket.available -= bytes as f64;
+
+            if bucket.available < 0.0 {
+                Duration::from_secs_f64(-bucket.available / limit as f64)
+            } else {
+                Duration::ZERO
+            }
+        };
+
+        if !wait.is_zero() {
+            tokio::time::sleep(wait).await;
+        }
+    }
+}
@@ -0,0 +1,258 @@
+use crate::jellyfin::errors::JellyfinError;
+use crate::jellyfin::models::{
+    ticks_to_seconds, GeneralCommand, LibraryUpdateInfo, PlayRequest, PlaystateRequest,
+    WebSocketMessage,
+};
+use crate::music_manager::MusicManager;
+use futures::{SinkExt, StreamExt};
+use serde_json::json;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tauri::{AppHandle, Emitter};
+use tokio_tungstenite::connect_async;
+use tokio_tungstenite::tungstenite::Message;
+
+const RECONNECT_DELAY: Duration = Duration::from_secs(10);
+const DEFAULT_KEEP_ALIVE: Duration = Duration::from_secs(30);
+
+// Commands forwarded to the player, sent as the "remote-command" event
+#[derive(Clone, serde::Serialize)]
+#[serde(tag = "command", rename_all = "camelCase")]
+enum RemoteCommand {
+    Play {
+        album_id: String,
+        track_index: usize,
+    },
+    Pause,
+    Unpause,
+    PlayPause,
+    Stop,
+    NextTrack,
+    PreviousTrack,
+    Seek {
+        position_seconds: f64,
+    },
+    SetVolume {
+        volume: u32,
+    },
+}
+
+async fn resolve_play_request(
+    request: PlayRequest,
+    music_manager: &MusicManager,
+    access_token: &str,
+) -> Result<Option<RemoteCommand>, JellyfinError> {
+    let start_index = request.start_index.unwrap_or(0) as usize;
+
+    let item_id = match request.item_ids.get(start_index) {
+        Some(item_id) => item_id,
+        None => return Ok(None),
+    };
+
+    let item = music_manager.get_item(item_id, access_token).await?;
+
+    match item.item_type.as_deref() {
+        Some("MusicAlbum") => Ok(Some(RemoteCommand::Play {
+            album_id: item.id,
+            track_index: 0,
+        })),
+        // the player works in albums, so play the track's album from that track
+        Some("Audio") => {
+            let album_id = match item.album_id {
+                Some(album_id) => album_id,
+                None => return Ok(None),
+            };
+
+            let tracks = music_manager
+                .get_tracks(&album_id, access_token, None, false)
+                .await?;
+
+            let track_index = tracks
+                .items
+                .iter()
+                .position(|track| track.id == item.id)
+                .unwrap_or(0);
+
+            Ok(Some(RemoteCommand::Play {
+                album_id,
+                track_index,
+            }))
+        }
+        _ => Ok(None),
+    }
+}
+
+fn parse_playstate_request(request: PlaystateRequest) -> Option<RemoteCommand> {
+    match request.command.as_str() {
+        "Pause" => Some(RemoteCommand::Pause),
+        "Unpause" => Some(RemoteCommand::Unpause),
+        "PlayPause" => Some(RemoteCommand::PlayPause),
+        "Stop" => Some(RemoteCommand::Stop),
+        "NextTrack" => Some(RemoteCommand::NextTrack),
+        "PreviousTrack" => Some(RemoteCommand::PreviousTrack),
+        "Seek" => request
+            .seek_position_ticks
+            .map(|ticks| RemoteCommand::Seek {
+                position_seconds: ticks_to_seconds(ticks),
+            }),
+        _ => None,
+    }
+}
+
+fn parse_general_command(command: GeneralCommand) -> Option<RemoteCommand> {
+    match command.name.as_str() {
+        "SetVolume" => command
+            .arguments
+            .get("Volume")
+            .and_then(|volume| volume.parse::<u32>().ok())
+            .map(|volume| RemoteCommand::SetVolume {
+                volume: volume.min(100),
+            }),
+        _ => None,
+    }
+}
+
+#[derive(Clone, serde::Serialize)]
+struct AlbumOutdated {
+    album_id: String,
+    reason: String,
+}
+
+fn emit_album_outdated(app_handle: &AppHandle, flagged: Vec<(String, String)>) {
+    for (album_id, reason) in flagged {
+        app_handle
+            .emit("album-outdated", AlbumOutdated { album_id, reason })
+            .unwrap();
+    }
+}
+
+async fn handle_message(
+    message: WebSocketMessage,
+    app_handle: &AppHandle,
+    music_manager: &MusicManager,
+    access_token: &str,
+) -> Result<(), JellyfinError> {
+    let command = match message.message_type.as_str() {
+        "Play" => {
+            let request = serde_json::from_value::<PlayRequest>(message.data)?;
+            resolve_play_request(request, music_manager, access_token).await?
+        }
+        "Playstate" => {
+            let request = serde_json::from_value::<PlaystateRequest>(message.data)?;
+            parse_playstate_request(request)
+        }
+        "GeneralCommand" => {
+            let command = serde_json::from_value::<GeneralCommand>(message.data)?;
+            parse_general_command(command)
+        }
+        "LibraryChanged" => {
+            let update = serde_json::from_value::<LibraryUpdateInfo>(message.data)?;
+            let flagged = music_manager
+                .handle_library_changes(&update, access_token)
+                .await?;
+
+            emit_album_outdated(app_handle, flagged);
+            None
+        }
+        _ => None,
+    };
+
+    if let Some(command) = command {
+        app_handle.emit("remote-command", command).unwrap();
+    }
+
+    Ok(())
+}
+
+// holds a single websocket session open until the server closes it or it errors
+async fn run_session(
+    app_handle: &AppHandle,
+    music_manager: &MusicManager,
+    access_token: &str,
+) -> Result<(), JellyfinError> {
+    music_manager.report_capabilities(access_token).await?;
+
+    let url = music_manager.get_websocket_url(access_token)?;
+
+    let (socket, _) = connect_async(url.as_str())
+        .await
+        .map_err(|e| JellyfinError::GenericError(format!("WebSocket connection failed: {}", e)))?;
+
+    // pick up anything that changed while we were disconnected
+    match music_manager.check_for_library_changes(access_token).await {
+        Ok(flagged) => emit_album_outdated(app_handle, flagged),
+        Err(e) => eprintln!("Error checking for library changes: {}", e),
+    }
+
+    let (mut writer, mut reader) = socket.split();
+    let mut keep_alive = tokio::time::interval(DEFAULT_KEEP_ALIVE);
+
+    loop {
+        tokio::select! {
+            _ = keep_alive.tick() => {
+                let keep_alive_message = json!({ "MessageType": "KeepAlive" }).to_string();
+
+                writer.send(Message::Text(keep_alive_message)).await.map_err(|e| {
+                    JellyfinError::GenericError(format!("WebSocket send failed: {}", e))
+                })?;
+            }
+            incoming = reader.next() => {
+                let incoming = match incoming {
+                    Some(incoming) => incoming.map_err(|e| {
+                        JellyfinError::GenericError(format!("WebSocket read failed: {}", e))
+                    })?,
+                    None => return Ok(()),
+                };
+
+                let text = match incoming {
+                    Message::Text(text) => text,
+                    Message::Close(_) => return Ok(()),
+                    _ => continue,
+                };
+
+                let message = match serde_json::from_str::<WebSocketMessage>(&text) {
+                    Ok(message) => message,
+                    Err(_) => continue,
+                };
+
+                // the server tells us how often it expects to hear from us
+                if message.message_type == "ForceKeepAlive" {
+                    if let Some(seconds) = message.data.as_u64().filter(|s| *s > 1) {
+                        keep_alive = tokio::time::interval(Duration::from_secs(seconds / 2));
+                    }
+                    continue;
+                }
+
+                let result = handle_message(message, app_handle, music_manager, access_token).await;
+
+                if let Err(e) = result {
+                    eprintln!("Error handling remote command: {}", e);
+                }
+            }
+        }
+    }
+}
+
+// The remote control loop, to be run in a thread. Reconnects whenever the
+// session drops, and waits for a login if there's no token yet.
+pub fn run_remote_control(
+    app_handle: AppHandle,
+    music_manager: Arc<MusicManager>,
+    auth_token: Arc<Mutex<Option<String>>>,
+) {
+    let rt = tokio::runtime::Runtime::new().unwrap();
+
+    rt.block_on(async {
+        loop {
+            let token = auth_token.lock().unwrap().clone();
+
+            if let Some(token) = token {
+                if let Err(e) = run_session(&app_handle, &music_manager, &token).await {
+                    eprintln!("Remote control session ended: {}", e);
+                }
+            }
+
+            tokio::time::sleep(RECONNECT_DELAY).await;
+        }
+    });
+}
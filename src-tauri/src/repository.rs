@@ -1,8 +1,12 @@
 use crate::db::Pool;
-use crate::models::{Album, NewAlbum, NewTrack, Track};
+use crate::models::{
+    Album, AlbumArtwork, AlbumMetadata, DownloadJob, DownloadRule, NewAlbum, NewDownloadJob,
+    NewDownloadRule, NewTrack, Track, JOB_DONE, JOB_QUEUED,
+};
 use crate::schema::albums::dsl as albums_dsl;
+use crate::schema::download_jobs::dsl as jobs_dsl;
+use crate::schema::download_rules::dsl as rules_dsl;
 use crate::schema::tracks::dsl as tracks_dsl;
-use chrono::Utc;
 use diesel::prelude::*;
 use thiserror::Error;
 
@@ -96,7 +100,8 @@ impl Repository {
             let tracks = tracks_dsl::tracks
                 .filter(tracks_dsl::album_id.eq(album.id))
                 .select(Track::as_select())
-                .order(tracks_dsl::track_index.asc())
+                .order(tracks_dsl::disc_number.asc())
+                .then_order_by(tracks_dsl::track_index.asc())
                 .load::<Track>(&mut conn)?;
             Ok(Some((album, tracks)))
         } else {
@@ -104,6 +109,33 @@ impl Repository {
         }
     }
 
+    pub fn find_track_with_album(
+        &self,
+        track_id: &str,
+    ) -> Result<Option<(Track, Album)>, RepositoryError> {
+        let mut conn = self.db_pool.get()?;
+        tracks_dsl::tracks
+            .inner_join(albums_dsl::albums)
+            .filter(tracks_dsl::jellyfin_id.eq(track_id))
+            .select((Track::as_select(), Album::as_select()))
+            .first::<(Track, Album)>(&mut conn)
+            .optional()
+            .map_err(RepositoryError::DbError)
+    }
+
+    pub fn set_track_digest(
+        &self,
+        track_id: i32,
+        size: i64,
+        sha256: &str,
+    ) -> Result<(), RepositoryError> {
+        let mut conn = self.db_pool.get()?;
+        diesel::update(tracks_dsl::tracks.filter(tracks_dsl::id.eq(track_id)))
+            .set((tracks_dsl::size.eq(size), tracks_dsl::sha256.eq(sha256)))
+            .execute(&mut conn)?;
+        Ok(())
+    }
+
     pub fn get_downloaded_album_ids(
         &self,
         album_ids: Vec<String>,
@@ -111,72 +143,452 @@ impl Repository {
         let mut conn = self.db_pool.get()?;
         albums_dsl::albums
             .filter(albums_dsl::jellyfin_id.eq_any(album_ids))
+            .filter(albums_dsl::path.is_not_null())
             .select(albums_dsl::jellyfin_id)
             .load(&mut conn)
             .map_err(RepositoryError::DbError)
     }
 
-    pub fn create_album(
+    // like get_downloaded_album_ids, but leaves out partial downloads, which
+    // have tracks without a path
+    pub fn get_fully_downloaded_album_ids(
         &self,
-        jellyfin_id_str: &str,
-        title_str: &str,
-        artist_str: &str,
-        image_id: Option<&str>,
-    ) -> Result<Album, RepositoryError> {
+        album_ids: Vec<String>,
+    ) -> Result<Vec<String>, RepositoryError> {
         let mut conn = self.db_pool.get()?;
+        albums_dsl::albums
+            .filter(albums_dsl::jellyfin_id.eq_any(album_ids))
+            .filter(albums_dsl::path.is_not_null())
+            .filter(diesel::dsl::not(diesel::dsl::exists(
+                tracks_dsl::tracks
+                    .filter(tracks_dsl::album_id.eq(albums_dsl::id))
+                    .filter(tracks_dsl::path.is_null()),
+            )))
+            .select(albums_dsl::jellyfin_id)
+            .load(&mut conn)
+            .map_err(RepositoryError::DbError)
+    }
 
-        let new_album = NewAlbum {
-            jellyfin_id: jellyfin_id_str,
-            title: title_str,
-            artist: artist_str,
-            image_id: image_id,
-            created_at: Utc::now().naive_utc(),
-            updated_at: Utc::now().naive_utc(),
-        };
+    pub fn get_downloaded_albums(&self) -> Result<Vec<Album>, RepositoryError> {
+        let mut conn = self.db_pool.get()?;
+        albums_dsl::albums
+            .filter(albums_dsl::path.is_not_null())
+            .select(Album::as_select())
+            .load::<Album>(&mut conn)
+            .map_err(RepositoryError::DbError)
+    }
+
+    // finds downloaded albums matching any of the ids, either directly or
+    // through one of their tracks
+    pub fn find_downloaded_albums_containing(
+        &self,
+        item_ids: &[String],
+    ) -> Result<Vec<Album>, RepositoryError> {
+        let mut conn = self.db_pool.get()?;
+
+        let track_album_ids = tracks_dsl::tracks
+            .filter(tracks_dsl::jellyfin_id.eq_any(item_ids))
+            .select(tracks_dsl::album_id);
+
+        albums_dsl::albums
+            .filter(albums_dsl::path.is_not_null())
+            .filter(
+                albums_dsl::jellyfin_id
+                    .eq_any(item_ids)
+                    .or(albums_dsl::id.eq_any(track_album_ids)),
+            )
+            .select(Album::as_select())
+            .load::<Album>(&mut conn)
+            .map_err(RepositoryError::DbError)
+    }
+
+    pub fn mark_album_as_outdated(
+        &self,
+        album_id: &str,
+        outdated: &str,
+    ) -> Result<(), RepositoryError> {
+        let mut conn = self.db_pool.get()?;
+        diesel::update(albums_dsl::albums.filter(albums_dsl::jellyfin_id.eq(album_id)))
+            .set(albums_dsl::outdated.eq(outdated))
+            .execute(&mut conn)?;
+        Ok(())
+    }
+
+    pub fn mark_albums_as_synced(&self, album_ids: &[String]) -> Result<(), RepositoryError> {
+        let mut conn = self.db_pool.get()?;
+        diesel::update(albums_dsl::albums.filter(albums_dsl::jellyfin_id.eq_any(album_ids)))
+            .set(albums_dsl::synced_at.eq(diesel::dsl::now))
+            .execute(&mut conn)?;
+        Ok(())
+    }
+
+    pub fn create_album(&self, new_album: &NewAlbum) -> Result<Album, RepositoryError> {
+        let mut conn = self.db_pool.get()?;
 
         diesel::insert_into(albums_dsl::albums)
-            .values(&new_album)
+            .values(new_album)
             .execute(&mut conn)?;
 
-        self.find_album(jellyfin_id_str)?.ok_or_else(|| {
+        self.find_album(new_album.jellyfin_id)?.ok_or_else(|| {
             RepositoryError::GenericError("Album not found after insertion".to_string())
         })
     }
 
-    pub fn insert_track(&self, new_track: &NewTrack) -> Result<(), RepositoryError> {
+    // saves the tracks and marks the album and its job done in one go, so an
+    // album is either complete or not downloaded at all. rows left by an earlier
+    // attempt are replaced
+    pub fn complete_album_download(
+        &self,
+        album_id: &str,
+        album_path: &str,
+        artwork: &AlbumArtwork,
+        quality: &str,
+        new_tracks: &[NewTrack],
+    ) -> Result<(), RepositoryError> {
         let mut conn = self.db_pool.get()?;
-        diesel::insert_into(tracks_dsl::tracks)
-            .values(new_track)
-            .execute(&mut conn)?;
-        Ok(())
+
+        conn.transaction::<_, RepositoryError, _>(|conn| {
+            let album = albums_dsl::albums
+                .filter(albums_dsl::jellyfin_id.eq(album_id))
+                .select(Album::as_select())
+                .first::<Album>(conn)?;
+
+            let track_ids = new_tracks.iter().map(|t| t.jellyfin_id).collect::<Vec<_>>();
+
+            diesel::delete(
+                tracks_dsl::tracks.filter(
+                    tracks_dsl::album_id
+                        .eq(album.id)
+                        .or(tracks_dsl::jellyfin_id.eq_any(&track_ids)),
+                ),
+            )
+            .execute(conn)?;
+
+            for new_track in new_tracks {
+                diesel::insert_into(tracks_dsl::tracks)
+                    .values(new_track)
+                    .execute(conn)?;
+            }
+
+            diesel::update(albums_dsl::albums.filter(albums_dsl::id.eq(album.id)))
+                .set((
+                    albums_dsl::path.eq(album_path),
+                    artwork,
+                    albums_dsl::quality.eq(quality),
+                    albums_dsl::synced_at.eq(diesel::dsl::now),
+                    albums_dsl::updated_at.eq(diesel::dsl::now),
+                ))
+                .execute(conn)?;
+
+            diesel::update(jobs_dsl::download_jobs.filter(jobs_dsl::album_id.eq(album_id)))
+                .set((
+                    jobs_dsl::status.eq(JOB_DONE),
+                    jobs_dsl::error.eq(None::<String>),
+                ))
+                .execute(conn)?;
+
+            Ok(())
+        })
     }
 
-    pub fn mark_album_as_downloaded(
+    // replaces a downloaded album's tracks and details with the server's, and
+    // clears any outdated flag
+    pub fn refresh_album(
         &self,
         album_id: &str,
+        metadata: &AlbumMetadata,
         album_path: &str,
-        image_path: Option<&str>,
+        artwork: &AlbumArtwork,
+        new_tracks: &[NewTrack],
     ) -> Result<(), RepositoryError> {
         let mut conn = self.db_pool.get()?;
+
+        conn.transaction::<_, RepositoryError, _>(|conn| {
+            let album = albums_dsl::albums
+                .filter(albums_dsl::jellyfin_id.eq(album_id))
+                .select(Album::as_select())
+                .first::<Album>(conn)?;
+
+            diesel::delete(tracks_dsl::tracks.filter(tracks_dsl::album_id.eq(album.id)))
+                .execute(conn)?;
+
+            for new_track in new_tracks {
+                diesel::insert_into(tracks_dsl::tracks)
+                    .values(new_track)
+                    .execute(conn)?;
+            }
+
+            diesel::update(albums_dsl::albums.filter(albums_dsl::id.eq(album.id)))
+                .set((
+                    metadata,
+                    albums_dsl::path.eq(album_path),
+                    artwork,
+                    albums_dsl::outdated.eq(None::<String>),
+                    albums_dsl::synced_at.eq(diesel::dsl::now),
+                    albums_dsl::updated_at.eq(diesel::dsl::now),
+                ))
+                .execute(conn)?;
+
+            Ok(())
+        })
+    }
+
+    pub fn delete_album_and_tracks(&self, album: &Album) -> Result<(), RepositoryError> {
+        let mut conn = self.db_pool.get()?;
+
+        diesel::delete(tracks_dsl::tracks.filter(tracks_dsl::album_id.eq(album.id)))
+            .execute(&mut conn)?;
+
+        diesel::delete(albums_dsl::albums.filter(albums_dsl::jellyfin_id.eq(&album.jellyfin_id)))
+            .execute(&mut conn)?;
+
+        diesel::delete(jobs_dsl::download_jobs.filter(jobs_dsl::album_id.eq(&album.jellyfin_id)))
+            .execute(&mut conn)?;
+
+        Ok(())
+    }
+
+    // the bytes taken up by downloaded tracks, for one album or all of them
+    pub fn get_downloaded_size(&self, album_id: Option<i32>) -> Result<u64, RepositoryError> {
+        let mut conn = self.db_pool.get()?;
+
+        let mut query = tracks_dsl::tracks
+            .inner_join(albums_dsl::albums)
+            .filter(albums_dsl::path.is_not_null())
+            .select(tracks_dsl::size)
+            .into_boxed();
+
+        if let Some(album_id) = album_id {
+            query = query.filter(albums_dsl::id.eq(album_id));
+        }
+
+        let sizes = query.load::<Option<i64>>(&mut conn)?;
+
+        Ok(sizes.into_iter().flatten().map(|size| size as u64).sum())
+    }
+
+    // downloaded albums that may be removed to make room, the ones to go first
+    // first. sqlite sorts albums that were never played before the rest
+    pub fn get_eviction_candidates(&self) -> Result<Vec<Album>, RepositoryError> {
+        let mut conn = self.db_pool.get()?;
+        albums_dsl::albums
+            .filter(albums_dsl::path.is_not_null())
+            .filter(albums_dsl::pinned.eq(false))
+            .order((
+                albums_dsl::last_played_at.asc(),
+                albums_dsl::updated_at.asc(),
+            ))
+            .select(Album::as_select())
+            .load::<Album>(&mut conn)
+            .map_err(RepositoryError::DbError)
+    }
+
+    pub fn mark_album_played(&self, album_id: &str) -> Result<(), RepositoryError> {
+        let mut conn = self.db_pool.get()?;
+        diesel::update(albums_dsl::albums.filter(albums_dsl::jellyfin_id.eq(album_id)))
+            .set(albums_dsl::last_played_at.eq(diesel::dsl::now))
+            .execute(&mut conn)?;
+        Ok(())
+    }
+
+    pub fn set_album_pinned(&self, album_id: &str, pinned: bool) -> Result<(), RepositoryError> {
+        let mut conn = self.db_pool.get()?;
         diesel::update(albums_dsl::albums.filter(albums_dsl::jellyfin_id.eq(album_id)))
+            .set(albums_dsl::pinned.eq(pinned))
+            .execute(&mut conn)?;
+        Ok(())
+    }
+
+    // albums that were started but never finished downloading
+    pub fn get_incomplete_albums(&self) -> Result<Vec<Album>, RepositoryError> {
+        let mut conn = self.db_pool.get()?;
+        albums_dsl::albums
+            .filter(albums_dsl::path.is_null())
+            .select(Album::as_select())
+            .load::<Album>(&mut conn)
+            .map_err(RepositoryError::DbError)
+    }
+
+    pub fn create_download_job(&self, new_job: &NewDownloadJob) -> Result<(), RepositoryError> {
+        let mut conn = self.db_pool.get()?;
+        diesel::insert_into(jobs_dsl::download_jobs)
+            .values(new_job)
+            .execute(&mut conn)?;
+        Ok(())
+    }
+
+    // in queue order: jobs moved to the front first, then oldest first
+    pub fn get_download_jobs(&self) -> Result<Vec<DownloadJob>, RepositoryError> {
+        let mut conn = self.db_pool.get()?;
+        jobs_dsl::download_jobs
+            .order((jobs_dsl::position.asc(), jobs_dsl::id.asc()))
+            .select(DownloadJob::as_select())
+            .load::<DownloadJob>(&mut conn)
+            .map_err(RepositoryError::DbError)
+    }
+
+    pub fn find_download_job(
+        &self,
+        album_id: &str,
+    ) -> Result<Option<DownloadJob>, RepositoryError> {
+        let mut conn = self.db_pool.get()?;
+        jobs_dsl::download_jobs
+            .filter(jobs_dsl::album_id.eq(album_id))
+            .select(DownloadJob::as_select())
+            .first(&mut conn)
+            .optional()
+            .map_err(RepositoryError::DbError)
+    }
+
+    // returns how many times the job has been tried, including this one
+    pub fn increment_download_job_attempts(&self, album_id: &str) -> Result<i32, RepositoryError> {
+        let mut conn = self.db_pool.get()?;
+
+        diesel::update(jobs_dsl::download_jobs.filter(jobs_dsl::album_id.eq(album_id)))
+            .set(jobs_dsl::attempts.eq(jobs_dsl::attempts + 1))
+            .execute(&mut conn)?;
+
+        jobs_dsl::download_jobs
+            .filter(jobs_dsl::album_id.eq(album_id))
+            .select(jobs_dsl::attempts)
+            .first(&mut conn)
+            .map_err(RepositoryError::DbError)
+    }
+
+    // puts a failed job back in the queue with a fresh set of attempts
+    pub fn reset_download_job(&self, album_id: &str) -> Result<(), RepositoryError> {
+        let mut conn = self.db_pool.get()?;
+        diesel::update(jobs_dsl::download_jobs.filter(jobs_dsl::album_id.eq(album_id)))
             .set((
-                albums_dsl::path.eq(album_path),
-                albums_dsl::image_path.eq(image_path),
-                albums_dsl::updated_at.eq(diesel::dsl::now),
+                jobs_dsl::status.eq(JOB_QUEUED),
+                jobs_dsl::error.eq(None::<String>),
+                jobs_dsl::attempts.eq(0),
             ))
             .execute(&mut conn)?;
         Ok(())
     }
 
-    pub fn delete_album_and_tracks(&self, album: &Album) -> Result<(), RepositoryError> {
+    pub fn set_download_job_tracks(
+        &self,
+        album_id: &str,
+        track_ids: Option<&str>,
+    ) -> Result<(), RepositoryError> {
         let mut conn = self.db_pool.get()?;
+        diesel::update(jobs_dsl::download_jobs.filter(jobs_dsl::album_id.eq(album_id)))
+            .set(jobs_dsl::track_ids.eq(track_ids))
+            .execute(&mut conn)?;
+        Ok(())
+    }
 
-        diesel::delete(tracks_dsl::tracks.filter(tracks_dsl::album_id.eq(album.id)))
+    pub fn set_download_job_status(
+        &self,
+        album_id: &str,
+        status: &str,
+        error: Option<&str>,
+    ) -> Result<(), RepositoryError> {
+        let mut conn = self.db_pool.get()?;
+        diesel::update(jobs_dsl::download_jobs.filter(jobs_dsl::album_id.eq(album_id)))
+            .set((jobs_dsl::status.eq(status), jobs_dsl::error.eq(error)))
             .execute(&mut conn)?;
+        Ok(())
+    }
 
-        diesel::delete(albums_dsl::albums.filter(albums_dsl::jellyfin_id.eq(&album.jellyfin_id)))
+    pub fn delete_download_jobs(&self, album_id: &str) -> Result<(), RepositoryError> {
+        let mut conn = self.db_pool.get()?;
+        diesel::delete(jobs_dsl::download_jobs.filter(jobs_dsl::album_id.eq(album_id)))
+            .execute(&mut conn)?;
+        Ok(())
+    }
+
+    pub fn move_download_job_to_front(&self, album_id: &str) -> Result<(), RepositoryError> {
+        let mut conn = self.db_pool.get()?;
+
+        let first = jobs_dsl::download_jobs
+            .select(diesel::dsl::min(jobs_dsl::position))
+            .first::<Option<i32>>(&mut conn)?
+            .unwrap_or(0);
+
+        diesel::update(jobs_dsl::download_jobs.filter(jobs_dsl::album_id.eq(album_id)))
+            .set(jobs_dsl::position.eq(first - 1))
+            .execute(&mut conn)?;
+        Ok(())
+    }
+
+    pub fn delete_finished_download_jobs(&self) -> Result<(), RepositoryError> {
+        let mut conn = self.db_pool.get()?;
+        diesel::delete(jobs_dsl::download_jobs.filter(jobs_dsl::status.eq(JOB_DONE)))
+            .execute(&mut conn)?;
+        Ok(())
+    }
+
+    pub fn delete_all_download_jobs(&self) -> Result<(), RepositoryError> {
+        let mut conn = self.db_pool.get()?;
+        diesel::delete(jobs_dsl::download_jobs).execute(&mut conn)?;
+        Ok(())
+    }
+
+    pub fn create_download_rule(
+        &self,
+        new_rule: &NewDownloadRule,
+    ) -> Result<DownloadRule, RepositoryError> {
+        let mut conn = self.db_pool.get()?;
+        diesel::insert_into(rules_dsl::download_rules)
+            .values(new_rule)
             .execute(&mut conn)?;
 
+        rules_dsl::download_rules
+            .order(rules_dsl::id.desc())
+            .select(DownloadRule::as_select())
+            .first::<DownloadRule>(&mut conn)
+            .map_err(RepositoryError::DbError)
+    }
+
+    pub fn get_download_rules(&self) -> Result<Vec<DownloadRule>, RepositoryError> {
+        let mut conn = self.db_pool.get()?;
+        rules_dsl::download_rules
+            .order(rules_dsl::id.asc())
+            .select(DownloadRule::as_select())
+            .load::<DownloadRule>(&mut conn)
+            .map_err(RepositoryError::DbError)
+    }
+
+    // albums the rule downloaded are kept, as if the user had downloaded them
+    pub fn delete_download_rule(&self, rule_id: i32) -> Result<(), RepositoryError> {
+        let mut conn = self.db_pool.get()?;
+
+        conn.transaction::<_, RepositoryError, _>(|conn| {
+            diesel::update(albums_dsl::albums.filter(albums_dsl::download_rule_id.eq(rule_id)))
+                .set(albums_dsl::download_rule_id.eq(None::<i32>))
+                .execute(conn)?;
+
+            diesel::delete(rules_dsl::download_rules.filter(rules_dsl::id.eq(rule_id)))
+                .execute(conn)?;
+
+            Ok(())
+        })
+    }
+
+    pub fn set_album_download_rule(
+        &self,
+        album_id: &str,
+        rule_id: i32,
+    ) -> Result<(), RepositoryError> {
+        let mut conn = self.db_pool.get()?;
+        diesel::update(albums_dsl::albums.filter(albums_dsl::jellyfin_id.eq(album_id)))
+            .set(albums_dsl::download_rule_id.eq(rule_id))
+            .execute(&mut conn)?;
         Ok(())
     }
+
+    // downloaded albums a rule fetched, that haven't been pinned to keep
+    pub fn get_rule_downloaded_albums(&self) -> Result<Vec<Album>, RepositoryError> {
+        let mut conn = self.db_pool.get()?;
+        albums_dsl::albums
+            .filter(albums_dsl::path.is_not_null())
+            .filter(albums_dsl::download_rule_id.is_not_null())
+            .filter(albums_dsl::pinned.eq(false))
+            .select(Album::as_select())
+            .load::<Album>(&mut conn)
+            .map_err(RepositoryError::DbError)
+    }
 }
@@ -0,0 +1,76 @@
+use crate::db::Pool;
+use crate::jellyfin::errors::JellyfinError;
+use crate::models::CachedResponse;
+use crate::schema::response_cache::dsl;
+use chrono::{Duration, Utc};
+use diesel::prelude::*;
+
+// Server responses kept in sqlite, keyed by request url. Fresh entries are
+// served without asking the server, older ones are revalidated with their etag,
+// and any entry can still be served (marked stale) when the server is down.
+pub struct ResponseCache {
+    db_pool: Pool,
+    ttl: Duration,
+}
+
+impl ResponseCache {
+    pub fn new(db_pool: Pool, ttl: Duration) -> Self {
+        Self { db_pool, ttl }
+    }
+
+    pub fn get(&self, key: &str) -> Result<Option<CachedResponse>, JellyfinError> {
+        let mut conn = self.db_pool.get()?;
+        dsl::response_cache
+            .filter(dsl::cache_key.eq(key))
+            .select(CachedResponse::as_select())
+            .first(&mut conn)
+            .optional()
+            .map_err(JellyfinError::DbError)
+    }
+
+    pub fn is_fresh(&self, entry: &CachedResponse) -> bool {
+        Utc::now().naive_utc() - entry.fetched_at < self.ttl
+    }
+
+    pub fn put(&self, key: &str, body: &str, etag: Option<&str>) -> Result<(), JellyfinError> {
+        let mut conn = self.db_pool.get()?;
+        diesel::replace_into(dsl::response_cache)
+            .values(&CachedResponse {
+                cache_key: key.to_string(),
+                body: body.to_string(),
+                etag: etag.map(String::from),
+                fetched_at: Utc::now().naive_utc(),
+            })
+            .execute(&mut conn)?;
+        Ok(())
+    }
+
+    // the server said the entry hasn't changed, so it's fresh again
+    pub fn touch(&self, key: &str) -> Result<(), JellyfinError> {
+        let mut conn = self.db_pool.get()?;
+        diesel::update(dsl::response_cache.filter(dsl::cache_key.eq(key)))
+            .set(dsl::fetched_at.eq(Utc::now().naive_utc()))
+            .execute(&mut conn)?;
+        Ok(())
+    }
+
+    // makes every entry revalidate on its next use, without throwing away the
+    // copies we'd fall back to when offline
+    pub fn expire_all(&self) -> Result<(), JellyfinError> {
+        let mut conn = self.db_pool.get()?;
+        let expired_at = Utc::now().naive_utc() - self.ttl;
+        diesel::update(dsl::response_cache.filter(dsl::fetched_at.gt(expired_at)))
+            .set(dsl::fetched_at.eq(expired_at))
+            .execute(&mut conn)?;
+        Ok(())
+    }
+
+    // drops entries that haven't been fetched or revalidated in a long time
+    pub fn prune(&self, max_age: Duration) -> Result<(), JellyfinError> {
+        let mut conn = self.db_pool.get()?;
+        let cutoff = Utc::now().naive_utc() - max_age;
+        diesel::delete(dsl::response_cache.filter(dsl::fetched_at.lt(cutoff)))
+            .execute(&mut conn)?;
+        Ok(())
+    }
+}
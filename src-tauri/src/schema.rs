@@ -11,6 +11,56 @@ diesel::table! {
         updated_at -> Timestamp,
         image_id -> Nullable<Text>,
         image_path -> Nullable<Text>,
+        quality -> Text,
+        outdated -> Nullable<Text>,
+        synced_at -> Nullable<Timestamp>,
+        production_year -> Nullable<Integer>,
+        genres -> Nullable<Text>,
+        artists -> Nullable<Text>,
+        run_time_ticks -> Nullable<BigInt>,
+        overview -> Nullable<Text>,
+        date_created -> Nullable<Timestamp>,
+        is_favorite -> Bool,
+        play_count -> Integer,
+        back_image_path -> Nullable<Text>,
+        disc_image_path -> Nullable<Text>,
+        artist_image_path -> Nullable<Text>,
+        last_played_at -> Nullable<Timestamp>,
+        pinned -> Bool,
+        download_rule_id -> Nullable<Integer>,
+    }
+}
+
+diesel::table! {
+    download_rules (id) {
+        id -> Integer,
+        criteria -> Text,
+        remove_unmatched -> Bool,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    download_jobs (id) {
+        id -> Integer,
+        album_id -> Text,
+        user_id -> Text,
+        quality -> Text,
+        created_at -> Timestamp,
+        status -> Text,
+        error -> Nullable<Text>,
+        attempts -> Integer,
+        position -> Integer,
+        track_ids -> Nullable<Text>,
+    }
+}
+
+diesel::table! {
+    response_cache (cache_key) {
+        cache_key -> Text,
+        body -> Text,
+        etag -> Nullable<Text>,
+        fetched_at -> Timestamp,
     }
 }
 
@@ -22,6 +72,14 @@ diesel::table! {
         album_id -> Integer,
         path -> Nullable<Text>,
         track_index -> Nullable<Integer>,
+        disc_number -> Nullable<Integer>,
+        artists -> Nullable<Text>,
+        run_time_ticks -> Nullable<BigInt>,
+        is_favorite -> Bool,
+        play_count -> Integer,
+        size -> Nullable<BigInt>,
+        sha256 -> Nullable<Text>,
+        date_modified -> Nullable<Timestamp>,
     }
 }
 
@@ -29,5 +87,8 @@ diesel::joinable!(tracks -> albums (album_id));
 
 diesel::allow_tables_to_appear_in_same_query!(
     albums,
+    download_jobs,
+    download_rules,
+    response_cache,
     tracks,
 );
@@ -0,0 +1,139 @@
+use crate::jellyfin::models::DownloadQuality;
+use chrono::NaiveTime;
+use serde::{Deserialize, Serialize};
+
+pub const DOWNLOAD_SETTINGS_KEY: &str = "download_settings";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct DownloadSettings {
+    pub quality: DownloadQuality,
+    // how many tracks of an album download at the same time
+    pub track_concurrency: usize,
+    // how many albums download at the same time
+    pub album_concurrency: usize,
+    // cap on the combined download rate, unlimited if not set
+    pub max_bytes_per_second: Option<u64>,
+    // queued albums only start inside one of these, or any time if there are none
+    pub download_windows: Vec<DownloadWindow>,
+    // the most the downloaded albums may take up. least recently played albums
+    // are removed to make room, unless they're pinned
+    pub library_quota_bytes: Option<u64>,
+}
+
+// a daily time range like 22:00 to 07:00, which may wrap past midnight. the
+// same start and end covers the whole day
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DownloadWindow {
+    pub start: String,
+    pub end: String,
+}
+
+impl DownloadWindow {
+    // windows that can't be parsed never match
+    pub fn contains(&self, time: NaiveTime) -> bool {
+        let (start, end) = match (
+            NaiveTime::parse_from_str(&self.start, "%H:%M"),
+            NaiveTime::parse_from_str(&self.end, "%H:%M"),
+        ) {
+            (Ok(start), Ok(end)) => (start, end),
+            _ => return false,
+        };
+
+        if start < end {
+            start <= time && time < end
+        } else {
+            time >= start || time < end
+        }
+    }
+}
+
+impl Default for DownloadSettings {
+    fn default() -> Self {
+        Self {
+            quality: DownloadQuality::default(),
+            track_concurrency: 2,
+            album_concurrency: 1,
+            max_bytes_per_second: None,
+            download_windows: Vec::new(),
+            library_quota_bytes: None,
+        }
+    }
+}
+
+impl DownloadSettings {
+    // falls back to the defaults if nothing has been saved yet, or if the saved
+    // value can't be read
+    pub fn from_store_value(value: Option<serde_json::Value>) -> Self {
+        let mut settings: Self = value
+            .and_then(|v| serde_json::from_value(v).ok())
+            .unwrap_or_default();
+
+        // a quality that can't be downloaded falls back to the original files
+        if settings.quality.validate().is_err() {
+            settings.quality = DownloadQuality::default();
+        }
+
+        settings
+    }
+
+    pub fn is_download_window_open(&self, time: NaiveTime) -> bool {
+        self.download_windows.is_empty()
+            || self
+                .download_windows
+                .iter()
+                .any(|window| window.contains(time))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn window(start: &str, end: &str) -> DownloadWindow {
+        DownloadWindow {
+            start: start.to_string(),
+            end: end.to_string(),
+        }
+    }
+
+    fn at(time: &str) -> NaiveTime {
+        NaiveTime::parse_from_str(time, "%H:%M").unwrap()
+    }
+
+    #[test]
+    fn window_includes_its_start_but_not_its_end() {
+        let window = window("09:00", "17:00");
+
+        assert!(window.contains(at("09:00")));
+        assert!(window.contains(at("12:30")));
+        assert!(!window.contains(at("17:00")));
+        assert!(!window.contains(at("08:59")));
+    }
+
+    #[test]
+    fn window_wraps_past_midnight() {
+        let window = window("22:00", "07:00");
+
+        assert!(window.contains(at("22:00")));
+        assert!(window.contains(at("00:00")));
+        assert!(window.contains(at("06:59")));
+        assert!(!window.contains(at("07:00")));
+        assert!(!window.contains(at("12:00")));
+    }
+
+    #[test]
+    fn window_with_the_same_start_and_end_covers_the_whole_day() {
+        let window = window("03:00", "03:00");
+
+        assert!(window.contains(at("03:00")));
+        assert!(window.contains(at("02:59")));
+        assert!(window.contains(at("15:00")));
+    }
+
+    #[test]
+    fn window_that_cant_be_parsed_never_matches() {
+        assert!(!window("25:00", "07:00").contains(at("23:00")));
+        assert!(!window("", "").contains(at("00:00")));
+    }
+}
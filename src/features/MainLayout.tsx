@@ -1,3 +1,4 @@
+import { invoke } from "@tauri-apps/api/core";
 import { Outlet } from "react-router";
 import Logo from "./components/Logo";
 import OfflineIcon from "./components/OfflineIcon";
@@ -18,6 +19,14 @@ function MainLayout() {
     <main className="container mx-auto p-4">
       <header className="relative">
         <Logo animated={isQueueActive} />
+        {isQueueActive && (
+          <button
+            onClick={() => invoke("cancel_all_downloads")}
+            className="absolute top-2 right-8 text-xs opacity-70 focus:opacity-100 hover:opacity-100"
+          >
+            Cancel downloads
+          </button>
+        )}
         <button
           onClick={handleOnlineToggle}
           className="absolute top-2 right-0 opacity-70 focus:opacity-100 hover:opacity-100"
@@ -2,19 +2,31 @@ import { useState } from "react";
 import { invoke } from "@tauri-apps/api/core";
 import { useNavigate } from "react-router";
 
+type LoginMode = "password" | "apiKey";
+
 function LoginPage() {
+  const [mode, setMode] = useState<LoginMode>("password");
   const [username, setUsername] = useState("");
   const [password, setPassword] = useState("");
+  const [apiKey, setApiKey] = useState("");
   const navigate = useNavigate();
 
   const handleSubmit = async (e: React.FormEvent) => {
     e.preventDefault();
 
     try {
-      await invoke("authenticate_user_by_name_cmd", {
-        username,
-        password,
-      });
+      if (mode === "apiKey") {
+        // the username field takes either a user name or a user id here
+        await invoke("authenticate_with_api_key_cmd", {
+          apiKey,
+          user: username,
+        });
+      } else {
+        await invoke("authenticate_user_by_name_cmd", {
+          username,
+          password,
+        });
+      }
       navigate("/search");
     } catch (error) {
       console.error("Login failed:", error);
@@ -31,19 +43,35 @@ function LoginPage() {
           type="text"
           value={username}
           onChange={(e) => setUsername(e.target.value)}
-          placeholder="Username"
+          placeholder={mode === "apiKey" ? "Username or user ID" : "Username"}
           required
         />
 
-        <input
-          type="password"
-          value={password}
-          onChange={(e) => setPassword(e.target.value)}
-          placeholder="Password"
-          required
-        />
+        {mode === "apiKey" ? (
+          <input
+            type="password"
+            value={apiKey}
+            onChange={(e) => setApiKey(e.target.value)}
+            placeholder="API key"
+            required
+          />
+        ) : (
+          <input
+            type="password"
+            value={password}
+            onChange={(e) => setPassword(e.target.value)}
+            placeholder="Password"
+            required
+          />
+        )}
 
         <button type="submit">Login</button>
+        <button
+          type="button"
+          onClick={() => setMode(mode === "apiKey" ? "password" : "apiKey")}
+        >
+          {mode === "apiKey" ? "Use a password" : "Use an API key"}
+        </button>
       </form>
     </main>
   );
@@ -6,6 +6,8 @@ export interface AlbumSearchResponse {
   items: AlbumSearchResponseItem[];
   totalRecordCount: number;
   startIndex: number;
+  // served from the cache because the server couldn't be reached
+  stale: boolean;
 }
 
 export interface AlbumSearchResponseItem {
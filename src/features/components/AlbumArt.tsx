@@ -0,0 +1,35 @@
+import { convertFileSrc } from "@tauri-apps/api/core";
+import PlayIcon from "./PlayIcon";
+
+// art comes back as a file path, or a thumbnail protocol url for online results
+export function albumArtSrc(imageUrl: string) {
+  return imageUrl.includes("://") ? imageUrl : convertFileSrc(imageUrl);
+}
+
+function AlbumArt({
+  imageUrl,
+  className,
+}: {
+  imageUrl?: string;
+  className: string;
+}) {
+  if (imageUrl) {
+    return (
+      <img
+        src={albumArtSrc(imageUrl)}
+        alt=""
+        className={`object-cover ${className}`}
+      />
+    );
+  }
+
+  return (
+    <div
+      className={`flex items-center justify-center bg-zinc-800 text-zinc-500 ${className}`}
+    >
+      <PlayIcon className="w-5 h-5" />
+    </div>
+  );
+}
+
+export default AlbumArt;
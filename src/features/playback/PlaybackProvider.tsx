@@ -1,4 +1,4 @@
-import { convertFileSrc } from "@tauri-apps/api/core";
+import { convertFileSrc, invoke } from "@tauri-apps/api/core";
 import {
   PropsWithChildren,
   createContext,
@@ -7,6 +7,8 @@ import {
   useRef,
   useState,
 } from "react";
+import { albumArtSrc } from "../components/AlbumArt";
+import { useRemoteControl } from "./useRemoteControl";
 
 type PlaybackContextType = {
   album: Album | null;
@@ -37,7 +39,7 @@ function setMediaSessionMedadata(
   albumArtUrl?: string
 ) {
   if ("mediaSession" in navigator) {
-    const albumArt = albumArtUrl ? convertFileSrc(albumArtUrl) : null;
+    const albumArt = albumArtUrl ? albumArtSrc(albumArtUrl) : null;
 
     navigator.mediaSession.metadata = new MediaMetadata({
       title,
@@ -55,6 +57,22 @@ function setMediaSessionMedadata(
   }
 }
 
+// the nearest playable track from index in the given direction, skipping
+// tracks a partial download left out
+function findPlayableTrack(
+  tracks: AlbumTrack[],
+  index: number,
+  step: 1 | -1
+): number | null {
+  for (let i = index; i >= 0 && i < tracks.length; i += step) {
+    if (tracks[i].available) {
+      return i;
+    }
+  }
+
+  return null;
+}
+
 export const PlaybackProvider = ({ children }: PropsWithChildren) => {
   const [album, setAlbum] = useState<Album | null>(null);
   const [track, setTrack] = useState<AlbumTrack | null>(null);
@@ -64,12 +82,26 @@ export const PlaybackProvider = ({ children }: PropsWithChildren) => {
   const [duration, setDuration] = useState(0);
   const audioRef = useRef<HTMLAudioElement | null>(null);
   const [autoPlay, setAutoPlay] = useState(false);
+  const [volume, setVolume] = useState(1);
+  const startIndexRef = useRef(0);
 
-  // selects first track when album is set
+  // selects the starting track (usually the first) when album is set
   useEffect(() => {
     if (album && album.tracks && album.tracks.length > 0) {
-      setTrackIndex(0);
+      setTrackIndex(
+        findPlayableTrack(
+          album.tracks,
+          Math.min(startIndexRef.current, album.tracks.length - 1),
+          1
+        )
+      );
+      startIndexRef.current = 0;
       setAutoPlay(true);
+
+      // keeps recently played albums from being removed to make room
+      invoke("mark_album_played", { albumId: album.id }).catch((error) =>
+        console.error("Failed to mark album as played:", error)
+      );
     } else {
       setTrackIndex(null);
     }
@@ -92,8 +124,12 @@ export const PlaybackProvider = ({ children }: PropsWithChildren) => {
 
     setCurrentTime(0);
 
-    const src = convertFileSrc(track.playbackUrl);
+    // streamed tracks come back as server urls, downloaded ones as file paths
+    const src = track.playbackUrl.startsWith("http")
+      ? track.playbackUrl
+      : convertFileSrc(track.playbackUrl);
     const audio = new Audio(src);
+    audio.volume = volume;
     audioRef.current = audio;
 
     const handlePlay = () => {
@@ -113,7 +149,8 @@ export const PlaybackProvider = ({ children }: PropsWithChildren) => {
     const handleEnded = () => {
       if (
         trackIndex !== null &&
-        trackIndex < (album?.tracks?.length ?? 0) - 1
+        album?.tracks &&
+        findPlayableTrack(album.tracks, trackIndex + 1, 1) !== null
       ) {
         handleNextTrack();
       } else {
@@ -183,27 +220,52 @@ export const PlaybackProvider = ({ children }: PropsWithChildren) => {
       return;
     }
 
-    const nextIndex = (trackIndex ?? 0) + 1;
-    if (nextIndex < album.tracks.length) {
+    const nextIndex = findPlayableTrack(album.tracks, (trackIndex ?? 0) + 1, 1);
+    if (nextIndex !== null) {
       setTrackIndex(nextIndex);
     }
   };
 
   const hasNextTrack =
-    trackIndex !== null && trackIndex < (album?.tracks?.length ?? 0) - 1;
+    trackIndex !== null &&
+    !!album?.tracks &&
+    findPlayableTrack(album.tracks, trackIndex + 1, 1) !== null;
 
   const handlePreviousTrack = () => {
     if (!album || !album.tracks || album.tracks.length === 0) {
       return;
     }
 
-    const prevIndex = (trackIndex ?? 0) - 1;
-    if (prevIndex >= 0) {
+    const prevIndex = findPlayableTrack(album.tracks, (trackIndex ?? 0) - 1, -1);
+    if (prevIndex !== null) {
       setTrackIndex(prevIndex);
     }
   };
 
-  const hasPreviousTrack = trackIndex !== null && trackIndex > 0;
+  const hasPreviousTrack =
+    trackIndex !== null &&
+    !!album?.tracks &&
+    findPlayableTrack(album.tracks, trackIndex - 1, -1) !== null;
+
+  useEffect(() => {
+    if (audioRef.current) {
+      audioRef.current.volume = volume;
+    }
+  }, [volume]);
+
+  useRemoteControl({
+    playAlbum: (album, index) => {
+      startIndexRef.current = index;
+      setAlbum(album);
+    },
+    play: handlePlay,
+    pause: handlePause,
+    togglePlayPause,
+    nextTrack: handleNextTrack,
+    previousTrack: handlePreviousTrack,
+    seek: handleSeek,
+    setVolume,
+  });
 
   useEffect(() => {
     if (!track || !album) {
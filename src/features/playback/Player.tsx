@@ -1,26 +1,30 @@
 import { useHotkeys } from "react-hotkeys-hook";
-import { convertFileSrc } from "@tauri-apps/api/core";
+import { albumArtSrc } from "../components/AlbumArt";
 import SpeakerIcon from "../components/SpeakerIcon";
 import Controls from "./Controls";
 import { usePlayback } from "./PlaybackProvider";
 import Seeker from "./Seeker";
 
 function Player() {
-  const { album, track, tracks, trackIndex, setTrackIndex, togglePlayPause } =
-    usePlayback();
+  const {
+    album,
+    track,
+    tracks,
+    trackIndex,
+    setTrackIndex,
+    togglePlayPause,
+    handleNextTrack,
+    handlePreviousTrack,
+  } = usePlayback();
 
   // j to select the next track
   useHotkeys("j", () => {
-    if (trackIndex !== null && trackIndex < tracks.length - 1) {
-      setTrackIndex(trackIndex + 1);
-    }
+    handleNextTrack();
   });
 
   // k to select the previous track
   useHotkeys("k", () => {
-    if (trackIndex !== null && trackIndex > 0) {
-      setTrackIndex(trackIndex - 1);
-    }
+    handlePreviousTrack();
   });
 
   // space to toggle play/pause
@@ -37,7 +41,7 @@ function Player() {
     return <div className="text-center text-2xl">No album selected</div>;
   }
 
-  const albumArt = album.imageUrl ? convertFileSrc(album.imageUrl) : null;
+  const albumArt = album.imageUrl ? albumArtSrc(album.imageUrl) : null;
 
   return (
     <div>
@@ -76,11 +80,14 @@ function Player() {
               key={index}
               type="button"
               onClick={() => handleTrackSelect(index)}
-              className={`flex w-full cursor-pointer items-baseline gap-2 border-l-2 px-3 py-2.5 text-left ${
+              disabled={!t.available}
+              className={`flex w-full items-baseline gap-2 border-l-2 px-3 py-2.5 text-left ${
                 isActive
                   ? "border-amber-300 bg-zinc-900 text-amber-300"
                   : "border-transparent text-zinc-100"
-              } ${index > 0 ? "border-t border-t-zinc-700/50" : ""}`}
+              } ${index > 0 ? "border-t border-t-zinc-700/50" : ""} ${
+                t.available ? "cursor-pointer" : "opacity-40"
+              }`}
             >
               <span
                 className={`w-8 shrink-0 tabular-nums ${
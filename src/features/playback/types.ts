@@ -1,11 +1,39 @@
 interface Album {
+  id: string;
   name: string;
   artist: string;
   tracks: AlbumTrack[];
   imageUrl?: string;
+  backImageUrl?: string;
+  discImageUrl?: string;
+  artistImageUrl?: string;
+  quality: string;
+  productionYear?: number;
+  genres: string[];
+  artists: string[];
+  durationSeconds?: number;
+  overview?: string;
+  dateCreated?: string;
+  isFavorite: boolean;
+  playCount: number;
+  discs: AlbumDisc[];
+}
+
+interface AlbumDisc {
+  discNumber: number;
+  startIndex: number;
+  trackCount: number;
 }
 
 interface AlbumTrack {
+  id: string;
   name: string;
   playbackUrl: string;
+  // false for tracks a partial download left out
+  available: boolean;
+  discNumber?: number;
+  artists: string[];
+  durationSeconds?: number;
+  isFavorite: boolean;
+  playCount: number;
 }
@@ -0,0 +1,84 @@
+import { useEffect, useRef } from "react";
+import { invoke } from "@tauri-apps/api/core";
+import { listen } from "@tauri-apps/api/event";
+
+type RemoteCommand =
+  | { command: "play"; album_id: string; track_index: number }
+  | { command: "pause" }
+  | { command: "unpause" }
+  | { command: "playPause" }
+  | { command: "stop" }
+  | { command: "nextTrack" }
+  | { command: "previousTrack" }
+  | { command: "seek"; position_seconds: number }
+  | { command: "setVolume"; volume: number };
+
+interface Handlers {
+  playAlbum: (album: Album, trackIndex: number) => void;
+  play: () => void;
+  pause: () => void;
+  togglePlayPause: () => void;
+  nextTrack: () => void;
+  previousTrack: () => void;
+  seek: (position: number) => void;
+  setVolume: (volume: number) => void;
+}
+
+// forwards commands from other jellyfin clients (web, phone) to the player
+export function useRemoteControl(handlers: Handlers) {
+  // keeps the listener pointed at the latest handlers without re-subscribing
+  const handlersRef = useRef(handlers);
+  handlersRef.current = handlers;
+
+  useEffect(() => {
+    let unlisten: (() => void) | undefined;
+
+    const setupListener = async () => {
+      unlisten = await listen<RemoteCommand>("remote-command", async (event) => {
+        const current = handlersRef.current;
+        const payload = event.payload;
+
+        switch (payload.command) {
+          case "play": {
+            const album = await invoke<Album>("get_album_info", {
+              albumId: payload.album_id,
+              online: true,
+            });
+            current.playAlbum(album, payload.track_index);
+            break;
+          }
+          case "pause":
+          case "stop":
+            current.pause();
+            break;
+          case "unpause":
+            current.play();
+            break;
+          case "playPause":
+            current.togglePlayPause();
+            break;
+          case "nextTrack":
+            current.nextTrack();
+            break;
+          case "previousTrack":
+            current.previousTrack();
+            break;
+          case "seek":
+            current.seek(payload.position_seconds);
+            break;
+          case "setVolume":
+            current.setVolume(payload.volume / 100);
+            break;
+        }
+      });
+    };
+
+    setupListener();
+
+    return () => {
+      if (unlisten) {
+        unlisten();
+      }
+    };
+  }, []);
+}
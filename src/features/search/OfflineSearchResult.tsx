@@ -1,6 +1,6 @@
-import { convertFileSrc } from "@tauri-apps/api/core";
 import { AlbumSearchResponseItem } from "../auth/types";
 import ActionButton from "../components/ActionButton";
+import AlbumArt from "../components/AlbumArt";
 import DeleteIcon from "../components/DeleteIcon";
 import PlayIcon from "../components/PlayIcon";
 import { useDownloadStatus } from "./useDownloadStatus";
@@ -13,32 +13,6 @@ interface Props {
   variant?: "list" | "card";
 }
 
-function AlbumArt({
-  imageUrl,
-  className,
-}: {
-  imageUrl?: string;
-  className: string;
-}) {
-  if (imageUrl) {
-    return (
-      <img
-        src={convertFileSrc(imageUrl)}
-        alt=""
-        className={`object-cover ${className}`}
-      />
-    );
-  }
-
-  return (
-    <div
-      className={`flex items-center justify-center bg-zinc-800 text-zinc-500 ${className}`}
-    >
-      <PlayIcon className="w-5 h-5" />
-    </div>
-  );
-}
-
 function OfflineSearchResult({
   item,
   handleDelete,
@@ -1,8 +1,14 @@
+import { invoke } from "@tauri-apps/api/core";
 import { AlbumSearchResponseItem } from "../auth/types";
 import ActionButton from "../components/ActionButton";
+import AlbumArt from "../components/AlbumArt";
 import CircleCheckIcon from "../components/CircleCheckIcon";
 import DownloadIcon from "../components/DownloadIcon";
-import { useDownloadStatus } from "./useDownloadStatus";
+import {
+  DownloadFailure,
+  DownloadProgress,
+  useDownloadStatus,
+} from "./useDownloadStatus";
 
 interface Props {
   item: AlbumSearchResponseItem;
@@ -10,13 +16,41 @@ interface Props {
   handleDelete: (id: string) => void;
 }
 
+function formatProgress(progress: DownloadProgress) {
+  const parts = [
+    `${progress.completed_tracks} of ${progress.total_tracks} tracks`,
+  ];
+
+  if (progress.bytes_per_second > 0) {
+    parts.push(`${(progress.bytes_per_second / 1024 / 1024).toFixed(1)} MB/s`);
+  }
+
+  if (progress.eta_seconds !== null) {
+    const minutes = Math.ceil(progress.eta_seconds / 60);
+    parts.push(`${minutes}m left`);
+  }
+
+  return parts.join(" · ");
+}
+
+const failureLabels: Record<DownloadFailure["reason"], string> = {
+  network: "couldn't reach the server",
+  server: "server error",
+  unauthorized: "not logged in",
+  notFound: "album not found",
+  other: "something went wrong",
+};
+
 function OnlineSearchResult({ item, handleDelete, handleDownload }: Props) {
-  const { isAlbumDownloading } = useDownloadStatus();
+  const { isAlbumDownloading, getAlbumProgress, getAlbumFailure } =
+    useDownloadStatus();
   const isDownloading = isAlbumDownloading(item.id);
+  const progress = isDownloading ? getAlbumProgress(item.id) : undefined;
+  const failure = isDownloading ? undefined : getAlbumFailure(item.id);
   const downloaded = item.downloaded && !isDownloading;
 
   return (
-    <div className="grid grid-cols-[auto_1fr] gap-x-2 items-start hover:bg-zinc-900 focus-within:bg-zinc-900 rounded p-2">
+    <div className="grid grid-cols-[auto_auto_1fr] gap-x-2 items-start hover:bg-zinc-900 focus-within:bg-zinc-900 rounded p-2">
       {downloaded ? (
         <ActionButton
           className="row-span-2 mt-1 focus:outline-none text-green-300 cursor-pointer"
@@ -36,8 +70,43 @@ function OnlineSearchResult({ item, handleDelete, handleDownload }: Props) {
           <DownloadIcon />
         </ActionButton>
       )}
+      <AlbumArt
+        imageUrl={item.imageUrl}
+        className="row-span-2 w-12 h-12 rounded"
+      />
       <div>{item.name}</div>
       <div className="opacity-70 font-light">{item.albumArtist}</div>
+      {failure && (
+        <div className="col-start-3 text-xs text-red-400">
+          Download failed: {failureLabels[failure.reason]}
+          {failure.will_retry ? (
+            ", retrying soon"
+          ) : (
+            <>
+              {" "}
+              <button
+                type="button"
+                className="underline cursor-pointer"
+                onClick={() => invoke("retry_download", { albumId: item.id })}
+              >
+                Retry
+              </button>
+            </>
+          )}
+        </div>
+      )}
+      {isDownloading && (
+        <div className="col-start-3 text-xs opacity-50">
+          {progress && formatProgress(progress)}{" "}
+          <button
+            type="button"
+            className="underline cursor-pointer"
+            onClick={() => invoke("cancel_download", { albumId: item.id })}
+          >
+            Cancel
+          </button>
+        </div>
+      )}
     </div>
   );
 }
@@ -100,7 +100,10 @@ function SearchPage() {
   };
 
   const handlePlay = async (id: string) => {
-    const album = await invoke<Album>("get_album_info", { albumId: id });
+    const album = await invoke<Album>("get_album_info", {
+      albumId: id,
+      online: isOnline,
+    });
     setAlbum(album);
     navigate("/player");
   };
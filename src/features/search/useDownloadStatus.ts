@@ -5,11 +5,43 @@ interface DownloadStatus {
   album_id: string;
 }
 
+export interface DownloadProgress {
+  album_id: string;
+  track_index: number;
+  total_tracks: number;
+  completed_tracks: number;
+  bytes_received: number;
+  bytes_total: number | null;
+  bytes_per_second: number;
+  eta_seconds: number | null;
+}
+
+export type DownloadFailureReason =
+  | "network"
+  | "server"
+  | "unauthorized"
+  | "notFound"
+  | "insufficientSpace"
+  | "other";
+
+export interface DownloadFailure {
+  album_id: string;
+  reason: DownloadFailureReason;
+  message: string;
+  will_retry: boolean;
+}
+
 export function useDownloadStatus() {
   const [isQueueActive, setIsQueueActive] = useState(false);
   const [currentlyDownloading, setCurrentlyDownloading] = useState<Set<string>>(
     new Set()
   );
+  const [progress, setProgress] = useState<Map<string, DownloadProgress>>(
+    new Map()
+  );
+  const [failures, setFailures] = useState<Map<string, DownloadFailure>>(
+    new Map()
+  );
 
   useEffect(() => {
     if (currentlyDownloading.size > 0) {
@@ -22,7 +54,9 @@ export function useDownloadStatus() {
   useEffect(() => {
     let unlistenAlbumDownloadStarted: () => void;
     let unlisetnAlbumDownloadFinished: () => void;
-    // albumDownloadError?
+    let unlistenDownloadProgress: () => void;
+    let unlistenAlbumDownloadCancelled: () => void;
+    let unlistenAlbumDownloadFailed: () => void;
 
     const setupListeners = async () => {
       unlistenAlbumDownloadStarted = await listen<DownloadStatus>(
@@ -34,6 +68,11 @@ export function useDownloadStatus() {
             newSet.add(event.payload.album_id);
             return newSet;
           });
+          setFailures((prev) => {
+            const newMap = new Map(prev);
+            newMap.delete(event.payload.album_id);
+            return newMap;
+          });
         }
       );
 
@@ -46,6 +85,60 @@ export function useDownloadStatus() {
             newSet.delete(event.payload.album_id);
             return newSet;
           });
+          setProgress((prev) => {
+            const newMap = new Map(prev);
+            newMap.delete(event.payload.album_id);
+            return newMap;
+          });
+        }
+      );
+
+      unlistenAlbumDownloadCancelled = await listen<DownloadStatus>(
+        "album-download-cancelled",
+        (event) => {
+          setCurrentlyDownloading((prev) => {
+            const newSet = new Set(prev);
+            newSet.delete(event.payload.album_id);
+            return newSet;
+          });
+          setProgress((prev) => {
+            const newMap = new Map(prev);
+            newMap.delete(event.payload.album_id);
+            return newMap;
+          });
+        }
+      );
+
+      unlistenAlbumDownloadFailed = await listen<DownloadFailure>(
+        "album-download-failed",
+        (event) => {
+          console.error("Album download failed", event);
+          setCurrentlyDownloading((prev) => {
+            const newSet = new Set(prev);
+            newSet.delete(event.payload.album_id);
+            return newSet;
+          });
+          setProgress((prev) => {
+            const newMap = new Map(prev);
+            newMap.delete(event.payload.album_id);
+            return newMap;
+          });
+          setFailures((prev) => {
+            const newMap = new Map(prev);
+            newMap.set(event.payload.album_id, event.payload);
+            return newMap;
+          });
+        }
+      );
+
+      unlistenDownloadProgress = await listen<DownloadProgress>(
+        "download-progress",
+        (event) => {
+          setProgress((prev) => {
+            const newMap = new Map(prev);
+            newMap.set(event.payload.album_id, event.payload);
+            return newMap;
+          });
         }
       );
     };
@@ -59,6 +152,15 @@ export function useDownloadStatus() {
       if (unlisetnAlbumDownloadFinished) {
         unlisetnAlbumDownloadFinished();
       }
+      if (unlistenDownloadProgress) {
+        unlistenDownloadProgress();
+      }
+      if (unlistenAlbumDownloadCancelled) {
+        unlistenAlbumDownloadCancelled();
+      }
+      if (unlistenAlbumDownloadFailed) {
+        unlistenAlbumDownloadFailed();
+      }
     };
   }, []); // The empty dependency array ensures this effect runs only once on mount.
 
@@ -66,5 +168,18 @@ export function useDownloadStatus() {
     return currentlyDownloading.has(albumId);
   };
 
-  return { isQueueActive, isAlbumDownloading };
+  const getAlbumProgress = (albumId: string): DownloadProgress | undefined => {
+    return progress.get(albumId);
+  };
+
+  const getAlbumFailure = (albumId: string): DownloadFailure | undefined => {
+    return failures.get(albumId);
+  };
+
+  return {
+    isQueueActive,
+    isAlbumDownloading,
+    getAlbumProgress,
+    getAlbumFailure,
+  };
 }
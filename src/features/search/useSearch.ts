@@ -56,8 +56,15 @@ export function useSearch(isOnline: boolean) {
       return;
     }
 
+    const newSummary = getSummary(
+      search,
+      res.totalRecordCount,
+      limit,
+      newOffset,
+      offlineView
+    );
     setSummary(
-      getSummary(search, res.totalRecordCount, limit, newOffset, offlineView)
+      res.stale ? `${newSummary} (cached, server unreachable)` : newSummary
     );
     setResults({
       ...res,